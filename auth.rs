@@ -0,0 +1,482 @@
+use crate::{Base64, Digest, Hmac, Sha256, Sha512, base64_decode, constant_time_eq, fill_random};
+use std::{env, fs, io, path::Path};
+
+/// Returns credentials of HTTP Basic authentication as (user, password)
+///
+/// The credentials are taken from the `Authorization` header, which a web server passes as `HTTP_AUTHORIZATION`.
+/// Some servers have to be configured to pass it. `None` is returned when there are no valid credentials.
+pub fn basic_credentials() -> Option<(String, String)> {
+    parse_basic_credentials(&env::var("HTTP_AUTHORIZATION").ok()?)
+}
+
+/// Parses a value of the `Authorization` header with the `Basic` scheme
+///
+/// # Examples
+/// ```
+/// assert_eq!(parse_basic_credentials("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="),
+///     Some(("Aladdin".to_string(), "open sesame".to_string())));
+/// ```
+pub fn parse_basic_credentials(header: &str) -> Option<(String, String)> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64_decode(token.trim())?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Returns a token of the `Authorization` header with the `Bearer` scheme
+///
+/// The header is taken from `HTTP_AUTHORIZATION` as for `basic_credentials`.
+pub fn bearer_token() -> Option<String> {
+    parse_bearer_token(&env::var("HTTP_AUTHORIZATION").ok()?)
+}
+
+/// Parses a value of the `Authorization` header with the `Bearer` scheme
+pub fn parse_bearer_token(header: &str) -> Option<String> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    let token = token.trim();
+    if !scheme.eq_ignore_ascii_case("bearer") || token.is_empty() {
+        return None;
+    }
+    Some(token.to_string())
+}
+
+/// Users and password hashes of an htpasswd style file
+///
+/// Every line has a form `user:hash`, empty lines and lines starting with `#` are ignored.
+/// Supported hashes: SHA-256 crypt (`$5$`), SHA-512 crypt (`$6$`) and PBKDF2 of `hash_password`.
+/// Users with other hashes can't be verified.
+/// An entry can be created by `mkpasswd -m sha-512` or `openssl passwd -6`.
+pub struct Htpasswd {
+    entries: Vec<(String, String)>,
+}
+
+impl Htpasswd {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(content: &str) -> Self {
+        Htpasswd {
+            entries: content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .filter_map(|line| line.split_once(':'))
+                .map(|(user, hash)| (user.to_string(), hash.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Checks a password of the user
+    ///
+    /// A password of an unknown user is hashed as well, so the response time doesn't tell
+    /// which users exist.
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        match self.entries.iter().find(|(name, _)| name == user) {
+            Some((_, hash)) => verify_hash(password, hash),
+            None => {
+                // a hash of the file has the same cost as hashes of existing users
+                let dummy = self
+                    .entries
+                    .first()
+                    .map_or(DUMMY_HASH, |(_, hash)| hash.as_str());
+                let _ = verify_hash(password, dummy);
+                false
+            }
+        }
+    }
+}
+
+/// An iteration count of PBKDF2-HMAC-SHA256 recommended by OWASP in 2023
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+const PBKDF2_PREFIX: &str = "$pbkdf2-sha256$";
+/// a hash of no password verified for unknown users of an empty file
+const DUMMY_HASH: &str =
+    "$pbkdf2-sha256$i=600000$AAAAAAAAAAAAAAAAAAAAAA$AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Hashes a password by PBKDF2-HMAC-SHA256 with a random salt
+///
+/// The result is a PHC string `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>`, where the salt
+/// and the hash are BASE64 without padding. It's checked by `verify_hash`.
+///
+/// # Examples
+/// ```
+/// let hash = hash_password("open sesame", PBKDF2_ITERATIONS)?;
+/// assert!(verify_hash("open sesame", &hash));
+/// assert!(!needs_rehash(&hash, PBKDF2_ITERATIONS));
+/// ```
+pub fn hash_password(password: &str, iterations: u32) -> io::Result<String> {
+    let iterations = iterations.max(1);
+    let mut salt = [0_u8; SALT_LEN];
+    fill_random(&mut salt)?;
+    let mut key = [0_u8; KEY_LEN];
+    pbkdf2_sha256(password.as_bytes(), &salt, iterations, &mut key);
+    let b64 = Base64::STANDARD_NO_PAD;
+    Ok(format! {"{PBKDF2_PREFIX}i={iterations}${}${}", b64.encode(&salt), b64.encode(&key)})
+}
+
+/// Tells that the hash should be replaced by `hash_password` on the next successful login
+///
+/// It's true for other hash types and for PBKDF2 hashes with fewer iterations than the policy.
+pub fn needs_rehash(hash: &str, iterations: u32) -> bool {
+    match parse_pbkdf2(hash) {
+        Some((hash_iterations, _, _)) => hash_iterations < iterations,
+        None => true,
+    }
+}
+
+/// Derives a key of the output length by PBKDF2 of RFC 8018 with HMAC-SHA256
+///
+/// # Examples
+/// ```
+/// let mut key = [0; 32];
+/// pbkdf2_sha256(b"password", b"salt", 1, &mut key);
+/// assert_eq!(to_hex(&key), "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b");
+/// ```
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    pbkdf2::<Sha256>(password, salt, iterations, out)
+}
+
+/// Derives a key of the output length by PBKDF2 of RFC 8018 with HMAC of the digest
+///
+/// # Examples
+/// ```
+/// // RFC 6070 test case 2
+/// let mut key = [0; 20];
+/// pbkdf2::<Sha1>(b"password", b"salt", 2, &mut key);
+/// assert_eq!(to_hex(&key), "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957");
+/// ```
+pub fn pbkdf2<D: Digest>(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    let keyed = Hmac::<D>::new(password);
+    let hash_len = D::default().finalize().as_ref().len();
+    for (i, block) in out.chunks_mut(hash_len).enumerate() {
+        let mut mac = keyed.clone();
+        mac.update(salt);
+        mac.update(&(i as u32 + 1).to_be_bytes());
+        let mut u = mac.finalize();
+        block.copy_from_slice(&u.as_ref()[..block.len()]);
+        for _ in 1..iterations {
+            let mut mac = keyed.clone();
+            mac.update(u.as_ref());
+            u = mac.finalize();
+            block.iter_mut().zip(u.as_ref()).for_each(|(t, u)| *t ^= u)
+        }
+    }
+}
+
+/// Returns iterations, a salt and a hash of a PHC string
+fn parse_pbkdf2(hash: &str) -> Option<(u32, Vec<u8>, Vec<u8>)> {
+    let mut parts = hash.strip_prefix(PBKDF2_PREFIX)?.split('$');
+    let (Some(params), Some(salt), Some(key), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let iterations = params
+        .split(',')
+        .find_map(|param| param.strip_prefix("i="))?
+        .parse()
+        .ok()
+        .filter(|&iterations| iterations > 0)?;
+    let key = Base64::STANDARD_NO_PAD.decode(key).ok()?;
+    if key.is_empty() || key.len() > 64 {
+        return None;
+    }
+    Some((iterations, Base64::STANDARD_NO_PAD.decode(salt).ok()?, key))
+}
+
+/// Checks a password against a crypt style or a PBKDF2 PHC hash
+///
+/// A password longer than 4096 bytes never matches, so a client can't make the hashing costly.
+pub fn verify_hash(password: &str, hash: &str) -> bool {
+    if password.len() > MAX_PASSWORD_LEN {
+        return false;
+    }
+    if hash.starts_with(PBKDF2_PREFIX) {
+        let Some((iterations, salt, key)) = parse_pbkdf2(hash) else {
+            return false;
+        };
+        let mut computed = vec![0; key.len()];
+        pbkdf2_sha256(password.as_bytes(), &salt, iterations, &mut computed);
+        return constant_time_eq(&computed, &key);
+    }
+    let computed = if hash.starts_with(SHA256_CRYPT.prefix) {
+        sha_crypt::<Sha256>(password, hash, &SHA256_CRYPT)
+    } else if hash.starts_with(SHA512_CRYPT.prefix) {
+        sha_crypt::<Sha512>(password, hash, &SHA512_CRYPT)
+    } else {
+        return false;
+    };
+    match computed {
+        Some(computed) => constant_time_eq(computed.as_bytes(), hash.as_bytes()),
+        None => false,
+    }
+}
+
+/// a limit of a verified password, SHA-crypt hashes the password length times the password
+const MAX_PASSWORD_LEN: usize = 4096;
+
+struct ShaCrypt {
+    prefix: &'static str,
+    /// an order of digest bytes in the encoding, by triples
+    order: &'static [u8],
+}
+
+const SHA256_CRYPT: ShaCrypt = ShaCrypt {
+    prefix: "$5$",
+    order: &[
+        0, 10, 20, 21, 1, 11, 12, 22, 2, 3, 13, 23, 24, 4, 14, 15, 25, 5, 6, 16, 26, 27, 7, 17, 18,
+        28, 8, 9, 19, 29, 31, 30,
+    ],
+};
+
+const SHA512_CRYPT: ShaCrypt = ShaCrypt {
+    prefix: "$6$",
+    order: &[
+        0, 21, 42, 22, 43, 1, 44, 2, 23, 3, 24, 45, 25, 46, 4, 47, 5, 26, 6, 27, 48, 28, 49, 7, 50,
+        8, 29, 9, 30, 51, 31, 52, 10, 53, 11, 32, 12, 33, 54, 34, 55, 13, 56, 14, 35, 15, 36, 57,
+        37, 58, 16, 59, 17, 38, 18, 39, 60, 40, 61, 19, 62, 20, 41, 63,
+    ],
+};
+
+const CRYPT_ALPHABET: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Computes a SHA-crypt hash of the Ulrich Drepper's specification using the setting of an existing hash
+///
+/// Repeated input is fed to the digest part by part, so nothing is allocated by the password length.
+fn sha_crypt<D: Digest>(password: &str, setting: &str, alg: &ShaCrypt) -> Option<String> {
+    let setting = setting.strip_prefix(alg.prefix)?;
+    let (rounds, setting) = match setting.strip_prefix("rounds=") {
+        Some(rest) => {
+            let (rounds, rest) = rest.split_once('$')?;
+            (
+                Some(rounds.parse::<u32>().ok()?.clamp(1000, 999_999_999)),
+                rest,
+            )
+        }
+        None => (None, setting),
+    };
+    let salt = setting.split('$').next()?;
+    let salt = &salt.as_bytes()[..salt.len().min(16)];
+    let pass = password.as_bytes();
+    let digest = |parts: &[&[u8]]| {
+        let mut hasher = D::default();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize()
+    };
+
+    let b = digest(&[pass, salt, pass]);
+    let b = b.as_ref();
+    let mut hasher = D::default();
+    hasher.update(pass);
+    hasher.update(salt);
+    let mut len = pass.len();
+    while len > b.len() {
+        hasher.update(b);
+        len -= b.len()
+    }
+    hasher.update(&b[..len]);
+    let mut len = pass.len();
+    while len > 0 {
+        if len & 1 == 1 {
+            hasher.update(b)
+        } else {
+            hasher.update(pass)
+        }
+        len >>= 1
+    }
+    let a = hasher.finalize();
+
+    let mut hasher = D::default();
+    (0..pass.len()).for_each(|_| hasher.update(pass));
+    let dp = hasher.finalize();
+    let p: Vec<u8> = dp
+        .as_ref()
+        .iter()
+        .cycle()
+        .take(pass.len())
+        .copied()
+        .collect();
+    let mut hasher = D::default();
+    (0..16 + a.as_ref()[0] as usize).for_each(|_| hasher.update(salt));
+    let ds = hasher.finalize();
+    let s = &ds.as_ref()[..salt.len()];
+
+    let mut c = a;
+    for i in 0..rounds.unwrap_or(5000) {
+        let mut hasher = D::default();
+        if i & 1 == 1 {
+            hasher.update(&p)
+        } else {
+            hasher.update(c.as_ref())
+        }
+        if i % 3 != 0 {
+            hasher.update(s)
+        }
+        if i % 7 != 0 {
+            hasher.update(&p)
+        }
+        if i & 1 == 1 {
+            hasher.update(c.as_ref())
+        } else {
+            hasher.update(&p)
+        }
+        c = hasher.finalize()
+    }
+    let c = c.as_ref();
+
+    let mut res = alg.prefix.to_string();
+    if let Some(rounds) = rounds {
+        res.push_str(&format! {"rounds={rounds}$"})
+    }
+    res.push_str(std::str::from_utf8(salt).ok()?);
+    res.push('$');
+    for triple in alg.order.chunks(3) {
+        let (mut w, n) = match *triple {
+            [b2, b1, b0] => (
+                (c[b2 as usize] as u32) << 16
+                    | (c[b1 as usize] as u32) << 8
+                    | c[b0 as usize] as u32,
+                4,
+            ),
+            [b1, b0] => ((c[b1 as usize] as u32) << 8 | c[b0 as usize] as u32, 3),
+            [b0] => (c[b0 as usize] as u32, 2),
+            _ => return None,
+        };
+        for _ in 0..n {
+            res.push(CRYPT_ALPHABET[(w & 0x3f) as usize] as char);
+            w >>= 6
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Sha1, to_hex};
+
+    /// settings, passwords and hashes of the SHA-crypt specification
+    const SHA_CRYPT_VECTORS: [(&str, &str, &str, &str); 7] = [
+        (
+            "saltstring",
+            "Hello world!",
+            "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5",
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1",
+        ),
+        (
+            "rounds=10000$saltstringsaltstring",
+            "Hello world!",
+            "$5$rounds=10000$saltstringsaltst$3xv.VbSHBb41AL9AvLeujZkZRBAwqFMz2.opqey6IcA",
+            "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v.",
+        ),
+        (
+            "rounds=5000$toolongsaltstring",
+            "This is just a test",
+            "$5$rounds=5000$toolongsaltstrin$Un/5jzAHMgOGZ5.mWJpuVolil07guHPvOW8mGRcvxa5",
+            "$6$rounds=5000$toolongsaltstrin$lQ8jolhgVRVhY4b5pZKaysCLi0QBxGoNeKQzQ3glMhwllF7oGDZxUhx1yxdYcz/e1JSbq3y6JMxxl8audkUEm0",
+        ),
+        (
+            "rounds=1400$anotherlongsaltstring",
+            "a very much longer text to encrypt.  This one even stretches over morethan one line.",
+            "$5$rounds=1400$anotherlongsalts$Rx.j8H.h8HjEDGomFU8bDkXm3XIUnzyxf12oP84Bnq1",
+            "$6$rounds=1400$anotherlongsalts$POfYwTEok97VWcjxIiSOjiykti.o/pQs.wPvMxQ6Fm7I6IoYN3CmLs66x9t0oSwbtEW7o7UmJEiDwGqd8p4ur1",
+        ),
+        (
+            "rounds=77777$short",
+            "we have a short salt string but not a short password",
+            "$5$rounds=77777$short$JiO1O3ZpDAxGJeaDIuqCoEFysAe1mZNJRs3pw0KQRd/",
+            "$6$rounds=77777$short$WuQyW2YR.hBNpjjRhpYD/ifIw05xdfeEyQoMxIXbkvr0gge1a1x3yRULJ5CCaUeOxFmtlcGZelFl5CxtgfiAc0",
+        ),
+        (
+            "rounds=123456$asaltof16chars..",
+            "a short string",
+            "$5$rounds=123456$asaltof16chars..$gP3VQ/6X7UUEW3HkBn2w1/Ptq2jxPyzV/cZKmF/wJvD",
+            "$6$rounds=123456$asaltof16chars..$BtCwjqMJGx5hrJhZywWvt0RLE8uZ4oPwcelCjmw2kSYu.Ec6ycULevoBK25fs2xXgMNrCzIMVcgEJAstJeonj1",
+        ),
+        (
+            "rounds=10$roundstoolow",
+            "the minimum number is still observed",
+            "$5$rounds=1000$roundstoolow$yfvwcWrQ8l/K0DAWyuPMDNHpIVlTQebY9l/gL972bIC",
+            "$6$rounds=1000$roundstoolow$kUMsbe306n21p9R.FRkW3IGn.S9NPN0x50YhH1xhLsPuWGsUSklZt58jaTfF4ZEQpyUNGc0dqbpBYYBaHHrsX.",
+        ),
+    ];
+
+    #[test]
+    fn sha_crypt_drepper() {
+        for (setting, password, sha256, sha512) in SHA_CRYPT_VECTORS {
+            let computed = sha_crypt::<Sha256>(password, &format! {"$5${setting}"}, &SHA256_CRYPT);
+            assert_eq!(computed.as_deref(), Some(sha256));
+            let computed = sha_crypt::<Sha512>(password, &format! {"$6${setting}"}, &SHA512_CRYPT);
+            assert_eq!(computed.as_deref(), Some(sha512));
+            assert!(verify_hash(password, sha256));
+            assert!(verify_hash(password, sha512));
+            assert!(!verify_hash("wrong", sha256));
+            assert!(!verify_hash("wrong", sha512));
+        }
+        let long = "x".repeat(MAX_PASSWORD_LEN + 1);
+        let hash = sha_crypt::<Sha256>(&long, "$5$saltstring", &SHA256_CRYPT).unwrap();
+        assert!(!verify_hash(&long, &hash));
+    }
+
+    #[test]
+    fn pbkdf2_rfc6070() {
+        let cases: [(&[u8], &[u8], u32, &str); 5] = [
+            (
+                b"password",
+                b"salt",
+                1,
+                "0c60c80f961f0e71f3a9b524af6012062fe037a6",
+            ),
+            (
+                b"password",
+                b"salt",
+                2,
+                "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957",
+            ),
+            (
+                b"password",
+                b"salt",
+                4096,
+                "4b007901b765489abead49d926f721d065a429c1",
+            ),
+            (
+                b"passwordPASSWORDpassword",
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                "3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038",
+            ),
+            (
+                b"pass\0word",
+                b"sa\0lt",
+                4096,
+                "56fa6aa75548099dcc37d7f03425e0c3",
+            ),
+        ];
+        for (password, salt, iterations, key) in cases {
+            let mut computed = vec![0; key.len() / 2];
+            pbkdf2::<Sha1>(password, salt, iterations, &mut computed);
+            assert_eq!(to_hex(&computed), key);
+        }
+    }
+
+    #[test]
+    fn pbkdf2_sha256_rfc7914() {
+        let mut key = [0; 64];
+        pbkdf2_sha256(b"passwd", b"salt", 1, &mut key);
+        assert_eq!(
+            to_hex(&key),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+        let hash = hash_password("secret", 1_000).unwrap();
+        assert!(verify_hash("secret", &hash));
+        assert!(!verify_hash("Secret", &hash));
+        assert!(needs_rehash(&hash, PBKDF2_ITERATIONS));
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+};
+
+const STANDARD_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const STANDARD_VALUES: &[u8; 256] = &values(STANDARD_ALPHABET);
+const URL_SAFE_VALUES: &[u8; 256] = &values(URL_SAFE_ALPHABET);
+const INVALID: u8 = 0xff;
+const CHUNK_LEN: usize = 4096;
+
+const fn values(alphabet: &[u8; 64]) -> [u8; 256] {
+    let mut res = [INVALID; 256];
+    let mut i = 0;
+    while i < 64 {
+        res[alphabet[i] as usize] = i as u8;
+        i += 1
+    }
+    res
+}
+
+/// Performs BASE64 encoding with padding.
+///
+///
+pub fn base64_encode_with_padding(input: &[u8]) -> String {
+    Base64::STANDARD.encode(input)
+}
+
+/// Performs BASE64 decoding, the padding and white spaces are optional.
+///
+/// `None` is returned when the input has a character out of the BASE64 alphabet or an invalid length.
+pub fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    Base64::STANDARD.lenient().decode(input).ok()
+}
+
+/// A kind of a BASE64 decoding error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Base64ErrorKind {
+    /// the byte isn't in the alphabet
+    InvalidByte(u8),
+    /// the padding is missing, misplaced or not allowed
+    InvalidPadding,
+    /// the input ends with a single symbol of a quantum
+    InvalidLength,
+    /// unused bits of the last symbol aren't zero, so the encoding isn't canonical
+    TrailingBits,
+}
+
+/// A BASE64 decoding error with a byte offset of the problem in the input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Base64Error {
+    pub position: usize,
+    pub kind: Base64ErrorKind,
+}
+
+impl Error for Base64Error {}
+
+impl fmt::Display for Base64Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Base64ErrorKind::InvalidByte(b) => {
+                write!(f, "invalid BASE64 byte 0x{b:02x} at {}", self.position)
+            }
+            Base64ErrorKind::InvalidPadding => {
+                write!(f, "invalid BASE64 padding at {}", self.position)
+            }
+            Base64ErrorKind::InvalidLength => write!(f, "invalid BASE64 length {}", self.position),
+            Base64ErrorKind::TrailingBits => {
+                write!(f, "non-zero BASE64 trailing bits at {}", self.position)
+            }
+        }
+    }
+}
+
+/// A BASE64 variant of RFC 4648
+///
+/// A strict decoder requires the exact padding of the variant and canonical trailing bits,
+/// and rejects white spaces. A lenient decoder skips white spaces, for example line breaks of MIME,
+/// and accepts data with or without the padding.
+///
+/// # Examples
+/// ```
+/// # use simweb::{Base64, Base64ErrorKind};
+/// # fn main() -> Result<(), simweb::Base64Error> {
+/// assert_eq!(Base64::STANDARD.encode(b"hello?"), "aGVsbG8/");
+/// assert_eq!(Base64::URL_SAFE_NO_PAD.encode(b"hi?"), "aGk_");
+/// assert_eq!(Base64::URL_SAFE_NO_PAD.decode("aGk_")?, b"hi?");
+/// let err = Base64::STANDARD.decode("aGk*").unwrap_err();
+/// assert_eq!((err.position, err.kind), (3, Base64ErrorKind::InvalidByte(b'*')));
+/// assert_eq!(Base64::STANDARD.lenient().decode("aGVs\r\nbG8")?, b"hello");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Base64 {
+    alphabet: &'static [u8; 64],
+    values: &'static [u8; 256],
+    padding: bool,
+    strict: bool,
+}
+
+impl Base64 {
+    /// `+` and `/` with the padding
+    pub const STANDARD: Base64 = Base64 {
+        alphabet: STANDARD_ALPHABET,
+        values: STANDARD_VALUES,
+        padding: true,
+        strict: true,
+    };
+    pub const STANDARD_NO_PAD: Base64 = Base64 {
+        padding: false,
+        ..Base64::STANDARD
+    };
+    /// `-` and `_` with the padding
+    pub const URL_SAFE: Base64 = Base64 {
+        alphabet: URL_SAFE_ALPHABET,
+        values: URL_SAFE_VALUES,
+        padding: true,
+        strict: true,
+    };
+    /// `-` and `_` without the padding, as used by JWT and URL tokens
+    pub const URL_SAFE_NO_PAD: Base64 = Base64 {
+        padding: false,
+        ..Base64::URL_SAFE
+    };
+
+    /// Returns the variant with lenient decoding
+    pub const fn lenient(self) -> Base64 {
+        Base64 {
+            strict: false,
+            ..self
+        }
+    }
+
+    pub fn encode(&self, input: &[u8]) -> String {
+        let mut res = String::with_capacity(input.len().div_ceil(3) * 4);
+        self.encode_to(input, &mut res);
+        res
+    }
+
+    fn encode_to(&self, input: &[u8], res: &mut String) {
+        let symbol = |val: u32| self.alphabet[(val & 0x3f) as usize] as char;
+        let mut chunks = input.chunks_exact(3);
+        for chunk in chunks.by_ref() {
+            let val = (chunk[0] as u32) << 16 | (chunk[1] as u32) << 8 | chunk[2] as u32;
+            res.extend([
+                symbol(val >> 18),
+                symbol(val >> 12),
+                symbol(val >> 6),
+                symbol(val),
+            ])
+        }
+        match *chunks.remainder() {
+            [b0] => {
+                let val = (b0 as u32) << 16;
+                res.extend([symbol(val >> 18), symbol(val >> 12)]);
+                if self.padding {
+                    res.push_str("==")
+                }
+            }
+            [b0, b1] => {
+                let val = (b0 as u32) << 16 | (b1 as u32) << 8;
+                res.extend([symbol(val >> 18), symbol(val >> 12), symbol(val >> 6)]);
+                if self.padding {
+                    res.push('=')
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn decode(&self, input: impl AsRef<[u8]>) -> Result<Vec<u8>, Base64Error> {
+        let input = input.as_ref();
+        let mut res = Vec::with_capacity(input.len() / 4 * 3 + 2);
+        let mut decoder = Decoder::new(*self);
+        for &b in input {
+            decoder.push(b, &mut res)?
+        }
+        decoder.finish(&mut res)?;
+        Ok(res)
+    }
+
+    /// Returns a writer encoding data into the inner writer
+    ///
+    /// `Base64Writer::finish` has to be called to write the last quantum and to see its errors,
+    /// otherwise it's written on the drop ignoring errors.
+    pub fn encoder<W: Write>(&self, inner: W) -> Base64Writer<W> {
+        Base64Writer {
+            inner: Some(inner),
+            config: *self,
+            pending: Vec::with_capacity(3),
+        }
+    }
+
+    /// Returns a reader decoding data of the inner reader
+    ///
+    /// An invalid input is reported as `ErrorKind::InvalidData` with `Base64Error` inside.
+    pub fn decoder<R: Read>(&self, inner: R) -> Base64Reader<R> {
+        Base64Reader {
+            inner,
+            decoder: Decoder::new(*self),
+            out: Vec::with_capacity(CHUNK_LEN / 4 * 3 + 3),
+            out_pos: 0,
+            done: false,
+        }
+    }
+}
+
+/// An incremental decoder shared by `Base64::decode` and `Base64Reader`
+struct Decoder {
+    config: Base64,
+    /// the number of consumed input bytes
+    pos: usize,
+    acc: u32,
+    /// symbols of the current quantum
+    symbols: usize,
+    pads: usize,
+    last_symbol: usize,
+}
+
+impl Decoder {
+    fn new(config: Base64) -> Self {
+        Decoder {
+            config,
+            pos: 0,
+            acc: 0,
+            symbols: 0,
+            pads: 0,
+            last_symbol: 0,
+        }
+    }
+
+    fn error(&self, position: usize, kind: Base64ErrorKind) -> Base64Error {
+        Base64Error { position, kind }
+    }
+
+    fn push(&mut self, b: u8, out: &mut Vec<u8>) -> Result<(), Base64Error> {
+        let pos = self.pos;
+        self.pos += 1;
+        if !self.config.strict && b.is_ascii_whitespace() {
+            return Ok(());
+        }
+        if b == b'=' {
+            if (self.config.strict && !self.config.padding)
+                || self.symbols < 2
+                || self.symbols + self.pads >= 4
+            {
+                return Err(self.error(pos, Base64ErrorKind::InvalidPadding));
+            }
+            self.pads += 1;
+            return Ok(());
+        }
+        let val = self.config.values[b as usize];
+        if val == INVALID {
+            return Err(self.error(pos, Base64ErrorKind::InvalidByte(b)));
+        }
+        if self.pads > 0 {
+            // data after the padding
+            return Err(self.error(pos, Base64ErrorKind::InvalidPadding));
+        }
+        self.acc = self.acc << 6 | val as u32;
+        self.symbols += 1;
+        self.last_symbol = pos;
+        if self.symbols == 4 {
+            out.extend_from_slice(&self.acc.to_be_bytes()[1..]);
+            self.acc = 0;
+            self.symbols = 0
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> Result<(), Base64Error> {
+        let (bytes, unused) = match self.symbols {
+            0 => return Ok(()),
+            1 => return Err(self.error(self.pos, Base64ErrorKind::InvalidLength)),
+            2 => (1, 4),
+            _ => (2, 2),
+        };
+        let padded = self.symbols + self.pads == 4;
+        if (self.pads > 0 && !padded) || (self.config.strict && self.config.padding && !padded) {
+            return Err(self.error(self.pos, Base64ErrorKind::InvalidPadding));
+        }
+        if self.config.strict && self.acc & ((1 << unused) - 1) != 0 {
+            return Err(self.error(self.last_symbol, Base64ErrorKind::TrailingBits));
+        }
+        let val = self.acc >> unused;
+        out.extend_from_slice(&val.to_be_bytes()[4 - bytes..]);
+        self.acc = 0;
+        self.symbols = 0;
+        Ok(())
+    }
+}
+
+/// A streaming BASE64 encoder created by `Base64::encoder`
+pub struct Base64Writer<W: Write> {
+    inner: Option<W>,
+    config: Base64,
+    /// bytes of an incomplete quantum
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Base64Writer<W> {
+    /// Writes the last quantum with the padding and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_tail()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn write_tail(&mut self) -> io::Result<()> {
+        let mut tail = String::new();
+        self.config.encode_to(&self.pending, &mut tail);
+        self.pending.clear();
+        match self.inner.as_mut() {
+            Some(inner) => inner.write_all(tail.as_bytes()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for Base64Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(inner) = self.inner.as_mut() else {
+            return Err(io::Error::other("the encoder is finished"));
+        };
+        let mut rest = buf;
+        let mut encoded = String::new();
+        if !self.pending.is_empty() {
+            let take = (3 - self.pending.len()).min(rest.len());
+            self.pending.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+            if self.pending.len() < 3 {
+                return Ok(buf.len());
+            }
+            self.config.encode_to(&self.pending, &mut encoded);
+            self.pending.clear()
+        }
+        let full = rest.len() - rest.len() % 3;
+        self.config.encode_to(&rest[..full], &mut encoded);
+        self.pending.extend_from_slice(&rest[full..]);
+        inner.write_all(encoded.as_bytes())?;
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer, an incomplete quantum stays until `finish`
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for Base64Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_tail();
+        }
+    }
+}
+
+/// A streaming BASE64 decoder created by `Base64::decoder`
+pub struct Base64Reader<R: Read> {
+    inner: R,
+    decoder: Decoder,
+    out: Vec<u8>,
+    out_pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for Base64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.out_pos < self.out.len() {
+                let len = buf.len().min(self.out.len() - self.out_pos);
+                buf[..len].copy_from_slice(&self.out[self.out_pos..self.out_pos + len]);
+                self.out_pos += len;
+                return Ok(len);
+            }
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.out.clear();
+            self.out_pos = 0;
+            let mut chunk = [0_u8; CHUNK_LEN];
+            let len = self.inner.read(&mut chunk)?;
+            let res = if len == 0 {
+                self.done = true;
+                self.decoder.finish(&mut self.out)
+            } else {
+                chunk[..len]
+                    .iter()
+                    .try_for_each(|&b| self.decoder.push(b, &mut self.out))
+            };
+            res.map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RFC4648: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    fn error(position: usize, kind: Base64ErrorKind) -> Result<Vec<u8>, Base64Error> {
+        Err(Base64Error { position, kind })
+    }
+
+    #[test]
+    fn rfc4648_vectors() {
+        for (plain, encoded) in RFC4648 {
+            assert_eq!(Base64::STANDARD.encode(plain.as_bytes()), encoded);
+            assert_eq!(
+                Base64::STANDARD.decode(encoded),
+                Ok(plain.as_bytes().to_vec())
+            );
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(Base64::STANDARD_NO_PAD.encode(plain.as_bytes()), unpadded);
+            assert_eq!(
+                Base64::URL_SAFE_NO_PAD.decode(unpadded),
+                Ok(plain.as_bytes().to_vec())
+            );
+            let lenient = Base64::STANDARD.lenient();
+            assert_eq!(lenient.decode(unpadded), Ok(plain.as_bytes().to_vec()));
+        }
+        assert_eq!(Base64::STANDARD.encode(&[0xfb, 0xff]), "+/8=");
+        assert_eq!(Base64::URL_SAFE.encode(&[0xfb, 0xff]), "-_8=");
+        assert_eq!(Base64::URL_SAFE.decode("-_8="), Ok(vec![0xfb, 0xff]));
+        assert_eq!(base64_decode("Zm9v\r\nYg"), Some(b"foob".to_vec()));
+    }
+
+    #[test]
+    fn invalid_padding() {
+        use Base64ErrorKind::*;
+        assert_eq!(Base64::STANDARD.decode("Zg"), error(2, InvalidPadding));
+        assert_eq!(Base64::STANDARD.decode("Zg="), error(3, InvalidPadding));
+        assert_eq!(Base64::STANDARD.decode("Z==="), error(1, InvalidPadding));
+        assert_eq!(Base64::STANDARD.decode("Zm9v="), error(4, InvalidPadding));
+        assert_eq!(Base64::STANDARD.decode("Zg===="), error(4, InvalidPadding));
+        assert_eq!(
+            Base64::STANDARD.decode("Zg==Zg=="),
+            error(4, InvalidPadding)
+        );
+        assert_eq!(
+            Base64::STANDARD_NO_PAD.decode("Zg=="),
+            error(2, InvalidPadding)
+        );
+        assert_eq!(
+            Base64::STANDARD.lenient().decode("Zg="),
+            error(3, InvalidPadding)
+        );
+        assert_eq!(
+            Base64::STANDARD_NO_PAD.decode("Zm9vY"),
+            error(5, InvalidLength)
+        );
+        assert_eq!(Base64::STANDARD.decode("Zh=="), error(1, TrailingBits));
+        assert_eq!(Base64::STANDARD.lenient().decode("Zh=="), Ok(b"f".to_vec()));
+    }
+
+    #[test]
+    fn invalid_characters() {
+        use Base64ErrorKind::*;
+        assert_eq!(Base64::STANDARD.decode("Zm9*"), error(3, InvalidByte(b'*')));
+        assert_eq!(Base64::STANDARD.decode("-_8="), error(0, InvalidByte(b'-')));
+        assert_eq!(Base64::URL_SAFE.decode("+/8="), error(0, InvalidByte(b'+')));
+        assert_eq!(
+            Base64::STANDARD.decode("Zm9v Yg=="),
+            error(4, InvalidByte(b' '))
+        );
+        assert_eq!(
+            Base64::STANDARD.decode("Zm9v\nYg=="),
+            error(4, InvalidByte(b'\n'))
+        );
+        assert_eq!(
+            Base64::STANDARD.lenient().decode("Zm9v\tY é"),
+            error(7, InvalidByte(0xc3))
+        );
+        assert_eq!(base64_decode("Zm9v!"), None);
+    }
+
+    #[test]
+    fn streaming() {
+        let data: Vec<u8> = (0..10_000_u32).map(|i| (i * 7 % 251) as u8).collect();
+        let encoded = Base64::STANDARD.encode(&data);
+        let mut encoder = Base64::STANDARD.encoder(Vec::new());
+        for piece in data.chunks(5).flat_map(|chunk| chunk.chunks(2)) {
+            encoder.write_all(piece).unwrap()
+        }
+        assert_eq!(encoder.finish().unwrap(), encoded.as_bytes());
+        let mut dropped = Vec::new();
+        Base64::URL_SAFE_NO_PAD
+            .encoder(&mut dropped)
+            .write_all(b"fooba")
+            .unwrap();
+        assert_eq!(dropped, b"Zm9vYmE");
+
+        let mut decoded = Vec::new();
+        let mut decoder = Base64::STANDARD.decoder(encoded.as_bytes());
+        let mut buf = [0; 7];
+        loop {
+            match decoder.read(&mut buf).unwrap() {
+                0 => break,
+                len => decoded.extend_from_slice(&buf[..len]),
+            }
+        }
+        assert_eq!(decoded, data);
+
+        let mut input = encoded.into_bytes();
+        input[5000] = b'*';
+        let err = Base64::STANDARD
+            .decoder(&input[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let inner = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<Base64Error>());
+        assert_eq!(
+            inner,
+            Some(&Base64Error {
+                position: 5000,
+                kind: Base64ErrorKind::InvalidByte(b'*')
+            })
+        );
+        let err = Base64::STANDARD
+            .decoder(&b"Zm9vYg="[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
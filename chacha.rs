@@ -0,0 +1,269 @@
+//! ChaCha20-Poly1305 authenticated encryption of RFC 8439
+use std::{error::Error, fmt};
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+/// An error of a decryption when data were altered or the key is wrong
+#[derive(Debug)]
+pub struct AeadError;
+
+impl Error for AeadError {}
+
+impl fmt::Display for AeadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "authentication of encrypted data failed")
+    }
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn chacha20_block(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN]) -> [u8; 64] {
+    let mut init = [0_u32; 16];
+    init[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        init[4 + i] = le_u32(&key[i * 4..])
+    }
+    init[12] = counter;
+    for i in 0..3 {
+        init[13 + i] = le_u32(&nonce[i * 4..])
+    }
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    let mut res = [0_u8; 64];
+    for i in 0..16 {
+        res[i * 4..i * 4 + 4].copy_from_slice(&s[i].wrapping_add(init[i]).to_le_bytes())
+    }
+    res
+}
+
+/// XORs data with the ChaCha20 key stream starting from the given block counter
+pub fn chacha20_xor(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let stream = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (b, s) in chunk.iter_mut().zip(stream) {
+            *b ^= s
+        }
+    }
+}
+
+/// Poly1305 one-time authenticator using 26 bit limbs
+fn poly1305(key: &[u8; 32], msg: &[u8]) -> [u8; TAG_LEN] {
+    let r0 = le_u32(&key[0..]) & 0x3ffffff;
+    let r1 = (le_u32(&key[3..]) >> 2) & 0x3ffff03;
+    let r2 = (le_u32(&key[6..]) >> 4) & 0x3ffc0ff;
+    let r3 = (le_u32(&key[9..]) >> 6) & 0x3f03fff;
+    let r4 = (le_u32(&key[12..]) >> 8) & 0x00fffff;
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+    let (mut h0, mut h1, mut h2, mut h3, mut h4) = (0_u32, 0_u32, 0_u32, 0_u32, 0_u32);
+
+    for chunk in msg.chunks(16) {
+        let mut block = [0_u8; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        block[chunk.len()] = 1;
+        h0 += le_u32(&block[0..]) & 0x3ffffff;
+        h1 += (le_u32(&block[3..]) >> 2) & 0x3ffffff;
+        h2 += (le_u32(&block[6..]) >> 4) & 0x3ffffff;
+        h3 += (le_u32(&block[9..]) >> 6) & 0x3ffffff;
+        h4 += (le_u32(&block[12..]) >> 8) | ((block[16] as u32) << 24);
+
+        let m = |a: u32, b: u32| a as u64 * b as u64;
+        let d0 = m(h0, r0) + m(h1, s4) + m(h2, s3) + m(h3, s2) + m(h4, s1);
+        let mut d1 = m(h0, r1) + m(h1, r0) + m(h2, s4) + m(h3, s3) + m(h4, s2);
+        let mut d2 = m(h0, r2) + m(h1, r1) + m(h2, r0) + m(h3, s4) + m(h4, s3);
+        let mut d3 = m(h0, r3) + m(h1, r2) + m(h2, r1) + m(h3, r0) + m(h4, s4);
+        let mut d4 = m(h0, r4) + m(h1, r3) + m(h2, r2) + m(h3, r1) + m(h4, r0);
+
+        let mut c = (d0 >> 26) as u32;
+        h0 = d0 as u32 & 0x3ffffff;
+        d1 += c as u64;
+        c = (d1 >> 26) as u32;
+        h1 = d1 as u32 & 0x3ffffff;
+        d2 += c as u64;
+        c = (d2 >> 26) as u32;
+        h2 = d2 as u32 & 0x3ffffff;
+        d3 += c as u64;
+        c = (d3 >> 26) as u32;
+        h3 = d3 as u32 & 0x3ffffff;
+        d4 += c as u64;
+        c = (d4 >> 26) as u32;
+        h4 = d4 as u32 & 0x3ffffff;
+        h0 += c * 5;
+        c = h0 >> 26;
+        h0 &= 0x3ffffff;
+        h1 += c
+    }
+
+    // full carry
+    let mut c = h1 >> 26;
+    h1 &= 0x3ffffff;
+    h2 += c;
+    c = h2 >> 26;
+    h2 &= 0x3ffffff;
+    h3 += c;
+    c = h3 >> 26;
+    h3 &= 0x3ffffff;
+    h4 += c;
+    c = h4 >> 26;
+    h4 &= 0x3ffffff;
+    h0 += c * 5;
+    c = h0 >> 26;
+    h0 &= 0x3ffffff;
+    h1 += c;
+
+    // compute h - p and select it when h >= p
+    let mut g0 = h0.wrapping_add(5);
+    c = g0 >> 26;
+    g0 &= 0x3ffffff;
+    let mut g1 = h1.wrapping_add(c);
+    c = g1 >> 26;
+    g1 &= 0x3ffffff;
+    let mut g2 = h2.wrapping_add(c);
+    c = g2 >> 26;
+    g2 &= 0x3ffffff;
+    let mut g3 = h3.wrapping_add(c);
+    c = g3 >> 26;
+    g3 &= 0x3ffffff;
+    let g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+
+    let mask = (g4 >> 31).wrapping_sub(1);
+    h0 = (h0 & !mask) | (g0 & mask);
+    h1 = (h1 & !mask) | (g1 & mask);
+    h2 = (h2 & !mask) | (g2 & mask);
+    h3 = (h3 & !mask) | (g3 & mask);
+    h4 = (h4 & !mask) | (g4 & mask);
+
+    let h0 = h0 | (h1 << 26);
+    let h1 = (h1 >> 6) | (h2 << 20);
+    let h2 = (h2 >> 12) | (h3 << 14);
+    let h3 = (h3 >> 18) | (h4 << 8);
+
+    let mut f = h0 as u64 + le_u32(&key[16..]) as u64;
+    let t0 = f as u32;
+    f = h1 as u64 + le_u32(&key[20..]) as u64 + (f >> 32);
+    let t1 = f as u32;
+    f = h2 as u64 + le_u32(&key[24..]) as u64 + (f >> 32);
+    let t2 = f as u32;
+    f = h3 as u64 + le_u32(&key[28..]) as u64 + (f >> 32);
+    let t3 = f as u32;
+
+    let mut tag = [0_u8; TAG_LEN];
+    for (i, t) in [t0, t1, t2, t3].iter().enumerate() {
+        tag[i * 4..i * 4 + 4].copy_from_slice(&t.to_le_bytes())
+    }
+    tag
+}
+
+fn compute_tag(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ct: &[u8],
+) -> [u8; TAG_LEN] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut otk = [0_u8; 32];
+    otk.copy_from_slice(&block[..32]);
+    let pad = |len: usize| (16 - len % 16) % 16;
+    let mut mac_data = Vec::with_capacity(aad.len() + ct.len() + 48);
+    mac_data.extend_from_slice(aad);
+    mac_data.resize(mac_data.len() + pad(aad.len()), 0);
+    mac_data.extend_from_slice(ct);
+    mac_data.resize(mac_data.len() + pad(ct.len()), 0);
+    mac_data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    mac_data.extend_from_slice(&(ct.len() as u64).to_le_bytes());
+    poly1305(&otk, &mac_data)
+}
+
+/// Encrypts the data and returns the ciphertext followed by the tag
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plain: &[u8]) -> Vec<u8> {
+    let mut res = plain.to_vec();
+    chacha20_xor(key, 1, nonce, &mut res);
+    let tag = compute_tag(key, nonce, aad, &res);
+    res.extend_from_slice(&tag);
+    res
+}
+
+/// Verifies the tag and decrypts the ciphertext produced by `seal`
+pub fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Result<Vec<u8>, AeadError> {
+    if sealed.len() < TAG_LEN {
+        return Err(AeadError);
+    }
+    let (ct, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    if !crate::hash::constant_time_eq(&compute_tag(key, nonce, aad, ct), tag) {
+        return Err(AeadError);
+    }
+    let mut res = ct.to_vec();
+    chacha20_xor(key, 1, nonce, &mut res);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_hex;
+
+    fn hex(val: &str) -> Vec<u8> {
+        from_hex(&val.replace([' ', '\n'], "")).unwrap()
+    }
+
+    #[test]
+    fn poly1305_rfc8439() {
+        let key = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+        let tag = poly1305(
+            &key.try_into().unwrap(),
+            b"Cryptographic Forum Research Group",
+        );
+        assert_eq!(tag.to_vec(), hex("a8061dc1305136c6c22b8baf0c0127a9"));
+    }
+
+    #[test]
+    fn aead_rfc8439() {
+        // section 2.8.2
+        let key: [u8; KEY_LEN] = std::array::from_fn(|i| 0x80 + i as u8);
+        let nonce: [u8; NONCE_LEN] = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plain = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip \
+            for the future, sunscreen would be it.";
+        let expected = hex("
+            d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6
+            3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36
+            92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc
+            3ff4def08e4b7a9de576d26586cec64b6116
+            1ae10b594f09e26a7e902ecbd0600691");
+        let sealed = seal(&key, &nonce, &aad, plain);
+        assert_eq!(sealed, expected);
+        assert_eq!(open(&key, &nonce, &aad, &sealed).unwrap(), plain);
+        let mut forged = sealed.clone();
+        forged[0] ^= 1;
+        assert!(open(&key, &nonce, &aad, &forged).is_err());
+        assert!(open(&key, &nonce, b"other", &sealed).is_err());
+    }
+}
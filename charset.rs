@@ -0,0 +1,146 @@
+use crate::WebError;
+
+/// the characters of Windows-1252 at 0x80..0xA0, undefined ones are C1 controls as in WHATWG Encoding
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// A character encoding of submitted text
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    /// every byte is a code point of the same value
+    Iso8859_1,
+    Windows1252,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// What to do with malformed input of a charset
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DecodePolicy {
+    /// malformed sequences are replaced by U+FFFD
+    #[default]
+    Lossy,
+    /// malformed input is an error
+    Strict,
+}
+
+impl Charset {
+    /// Returns a charset by its name, the name is case insensitive
+    ///
+    /// `None` is returned for an unsupported charset. `UTF-16` without an order means
+    /// little endian, since a byte order mark takes precedence anyway.
+    pub fn from_label(label: &str) -> Option<Charset> {
+        let label = label.trim().to_ascii_lowercase();
+        Some(match label.as_str() {
+            "utf-8" | "utf8" | "unicode-1-1-utf-8" => Charset::Utf8,
+            "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1" | "us-ascii" | "ascii" => {
+                Charset::Iso8859_1
+            }
+            "windows-1252" | "cp1252" | "x-cp1252" => Charset::Windows1252,
+            "utf-16" | "utf-16le" | "utf16" | "utf16le" => Charset::Utf16Le,
+            "utf-16be" | "utf16be" => Charset::Utf16Be,
+            _ => return None,
+        })
+    }
+
+    /// Returns the preferred name of the charset
+    pub fn name(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Iso8859_1 => "ISO-8859-1",
+            Charset::Windows1252 => "windows-1252",
+            Charset::Utf16Le => "UTF-16LE",
+            Charset::Utf16Be => "UTF-16BE",
+        }
+    }
+
+    /// Decodes bytes to a string, a byte order mark of UTF-8 or UTF-16 is removed
+    ///
+    /// A UTF-16 byte order mark overrides the byte order of the charset.
+    /// The error of the strict policy tells a byte position of the malformed input.
+    ///
+    /// # Examples
+    /// ```
+    /// assert_eq!(Charset::Windows1252.decode(b"\x93caf\xe9\x94", DecodePolicy::Strict)?, "“café”");
+    /// assert_eq!(Charset::Utf8.decode(b"caf\xe9", DecodePolicy::Lossy)?, "caf\u{fffd}");
+    /// assert!(Charset::Utf8.decode(b"caf\xe9", DecodePolicy::Strict).is_err());
+    /// ```
+    pub fn decode(&self, bytes: &[u8], policy: DecodePolicy) -> Result<String, WebError> {
+        match self {
+            Charset::Utf8 => {
+                let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+                match std::str::from_utf8(bytes) {
+                    Ok(text) => Ok(text.to_string()),
+                    Err(err) if policy == DecodePolicy::Strict => {
+                        Err(malformed(*self, err.valid_up_to()))
+                    }
+                    Err(_) => Ok(String::from_utf8_lossy(bytes).to_string()),
+                }
+            }
+            Charset::Iso8859_1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+            Charset::Windows1252 => Ok(bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..0xa0 => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    b => b as char,
+                })
+                .collect()),
+            Charset::Utf16Le | Charset::Utf16Be => {
+                let (bytes, big_endian) = match bytes {
+                    [0xff, 0xfe, rest @ ..] => (rest, false),
+                    [0xfe, 0xff, rest @ ..] => (rest, true),
+                    _ => (bytes, *self == Charset::Utf16Be),
+                };
+                let units = bytes.chunks(2).map(|pair| match (pair, big_endian) {
+                    ([hi, lo], true) | ([lo, hi], false) => Ok(u16::from_be_bytes([*hi, *lo])),
+                    // an odd length
+                    _ => Err(()),
+                });
+                let mut res = String::with_capacity(bytes.len() / 2);
+                let mut pos = 0;
+                let mut pending: Option<u16> = None;
+                let mut units = units.peekable();
+                while let Some(unit) = units.next() {
+                    let c = match (unit, pending.take()) {
+                        (Ok(low @ 0xdc00..0xe000), Some(high)) => char::from_u32(
+                            0x10000 + (((high as u32) - 0xd800) << 10) + (low as u32 - 0xdc00),
+                        ),
+                        (Ok(high @ 0xd800..0xdc00), None)
+                            if units.peek().is_some_and(|next| {
+                                next.is_ok_and(|next| (0xdc00..0xe000).contains(&next))
+                            }) =>
+                        {
+                            pending = Some(high);
+                            pos += 2;
+                            continue;
+                        }
+                        (Ok(unit), _) => char::from_u32(unit as u32),
+                        (Err(()), _) => None,
+                    };
+                    match c {
+                        Some(c) => res.push(c),
+                        None if policy == DecodePolicy::Strict => {
+                            return Err(malformed(*self, pos));
+                        }
+                        None => res.push(char::REPLACEMENT_CHARACTER),
+                    }
+                    pos += 2
+                }
+                Ok(res)
+            }
+        }
+    }
+}
+
+fn malformed(charset: Charset, position: usize) -> WebError {
+    WebError {
+        reason: format! {"malformed {} at {position}", charset.name()},
+        cause: None,
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{Base64, Crc32, Md5, PartHeaders, Sha256, WebError, to_hex};
+use std::io::{self, Read};
+
+/// A digest calculated while content is received
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumKind {
+    Sha256,
+    /// for legacy clients sending `Content-MD5`
+    Md5,
+    /// CRC-32 of ZIP and gzip
+    Crc32,
+}
+
+/// Digests of received content, only requested ones and ones needed to verify headers are present
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checksums {
+    pub sha256: Option<[u8; 32]>,
+    pub md5: Option<[u8; 16]>,
+    pub crc32: Option<u32>,
+}
+
+impl Checksums {
+    /// Returns a digest in hex, CRC-32 is big endian
+    pub fn hex(&self, kind: ChecksumKind) -> Option<String> {
+        match kind {
+            ChecksumKind::Sha256 => self.sha256.map(|sha| to_hex(&sha)),
+            ChecksumKind::Md5 => self.md5.map(|md5| to_hex(&md5)),
+            ChecksumKind::Crc32 => self.crc32.map(|crc| to_hex(&crc.to_be_bytes())),
+        }
+    }
+
+    /// Compares the digests with `Content-MD5`, `Digest` and `Repr-Digest` headers
+    ///
+    /// Algorithms other than SHA-256 and MD5 of the headers are ignored.
+    /// A mismatch or a malformed value is an error.
+    ///
+    /// # Examples
+    /// ```
+    /// // Content-MD5: XUFAKrxLKna5cZ2REBfFkg==
+    /// // Repr-Digest: sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:
+    /// part_checksums.verify(part.headers())?;
+    /// ```
+    pub fn verify(&self, headers: &PartHeaders) -> Result<(), WebError> {
+        for ExpectedDigest {
+            header,
+            kind,
+            digest,
+        } in expected_digests(headers)?
+        {
+            let actual = match kind {
+                ChecksumKind::Sha256 => self.sha256.map(|sha| sha.to_vec()),
+                ChecksumKind::Md5 => self.md5.map(|md5| md5.to_vec()),
+                ChecksumKind::Crc32 => self.crc32.map(|crc| crc.to_be_bytes().to_vec()),
+            };
+            if actual.is_some_and(|actual| actual != digest) {
+                return Err(WebError {
+                    reason: format! {"{kind:?} of the content doesn't match {header}"},
+                    cause: None,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A reader calculating digests of the inner reader data
+///
+/// Besides the requested kinds, digests given by headers of the part are calculated,
+/// so `Checksums::verify` can check them.
+pub struct ChecksumReader<R: Read> {
+    inner: R,
+    sha256: Option<Sha256>,
+    md5: Option<Md5>,
+    crc32: Option<Crc32>,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(inner: R, kinds: &[ChecksumKind], headers: &PartHeaders) -> Self {
+        let mut kinds = kinds.to_vec();
+        if let Ok(expected) = expected_digests(headers) {
+            kinds.extend(expected.into_iter().map(|expected| expected.kind))
+        }
+        ChecksumReader {
+            inner,
+            sha256: kinds.contains(&ChecksumKind::Sha256).then(Sha256::new),
+            md5: kinds.contains(&ChecksumKind::Md5).then(Md5::new),
+            crc32: kinds.contains(&ChecksumKind::Crc32).then(Crc32::new),
+        }
+    }
+
+    /// Returns digests of all data read so far
+    pub fn finish(self) -> Checksums {
+        Checksums {
+            sha256: self.sha256.map(Sha256::finalize),
+            md5: self.md5.map(Md5::finalize),
+            crc32: self.crc32.map(Crc32::finalize),
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        let data = &buf[..len];
+        if let Some(sha) = &mut self.sha256 {
+            sha.update(data)
+        }
+        if let Some(md5) = &mut self.md5 {
+            md5.update(data)
+        }
+        if let Some(crc) = &mut self.crc32 {
+            crc.update(data)
+        }
+        Ok(len)
+    }
+}
+
+/// A digest given by a header
+struct ExpectedDigest {
+    header: &'static str,
+    kind: ChecksumKind,
+    digest: Vec<u8>,
+}
+
+/// Collects digests of `Content-MD5` (RFC 1864), `Digest` (RFC 3230) and `Repr-Digest` (RFC 9530)
+fn expected_digests(headers: &PartHeaders) -> Result<Vec<ExpectedDigest>, WebError> {
+    let decode = |header, val: &str| {
+        Base64::STANDARD
+            .lenient()
+            .decode(val.trim())
+            .map_err(|err| WebError {
+                reason: format! {"malformed {header}: {err}"},
+                cause: None,
+            })
+    };
+    let mut res = Vec::new();
+    if let Some(md5) = headers.get("content-md5") {
+        res.push(ExpectedDigest {
+            header: "Content-MD5",
+            kind: ChecksumKind::Md5,
+            digest: decode("Content-MD5", md5)?,
+        })
+    }
+    for (header, structured) in [("Digest", false), ("Repr-Digest", true)] {
+        let Some(val) = headers.get(header) else {
+            continue;
+        };
+        for item in val.split(',') {
+            let Some((alg, digest)) = item.split_once('=') else {
+                continue;
+            };
+            let kind = match alg.trim().to_ascii_lowercase().as_str() {
+                "sha-256" => ChecksumKind::Sha256,
+                "md5" => ChecksumKind::Md5,
+                _ => continue,
+            };
+            // a byte sequence of structured fields is `:base64:` followed by parameters
+            let digest = if structured {
+                let digest = digest.split(';').next().unwrap_or_default().trim();
+                digest
+                    .strip_prefix(':')
+                    .and_then(|digest| digest.strip_suffix(':'))
+                    .ok_or_else(|| WebError {
+                        reason: format! {"malformed {header}: {digest}"},
+                        cause: None,
+                    })?
+            } else {
+                digest
+            };
+            res.push(ExpectedDigest {
+                header,
+                kind,
+                digest: decode(header, digest)?,
+            })
+        }
+    }
+    Ok(res)
+}
@@ -0,0 +1,773 @@
+use crate::{
+    WebError, chacha,
+    data::http_format_time,
+    from_hex,
+    hash::{constant_time_eq, hmac_sha256},
+    random::fill_random,
+    to_hex, url_encode,
+};
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// Values of the `SameSite` cookie attribute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// requires `Secure`
+    None,
+}
+
+/// A builder of a `Set-Cookie` header
+///
+/// A new cookie is safe by default: `Path=/; Secure; HttpOnly; SameSite=Lax`,
+/// and it's a session cookie until `max_age` or `expires` is specified.
+///
+/// # Examples
+/// ```
+/// let (set_op, val) = Cookie::new("sid", "a1b2c3")
+///     .max_age(Duration::from_secs(3600))
+///     .same_site(Some(SameSite::Strict))
+///     .host_prefixed()
+///     .header()?;
+/// assert_eq!(val, "__Host-sid=a1b2c3; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Strict");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    partitioned: bool,
+    /// a plain value of a signed or encrypted cookie, it's protected again when a prefix changes the name
+    protected: Option<Protected>,
+}
+
+#[derive(Clone)]
+struct Protected {
+    keys: CookieKeys,
+    plain: String,
+    /// a nonce of an encrypted value
+    nonce: Option<[u8; chacha::NONCE_LEN]>,
+}
+
+impl fmt::Debug for Protected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Protected")
+            .field("encrypted", &self.nonce.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            path: Some("/".to_string()),
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: true,
+            http_only: true,
+            same_site: Some(SameSite::Lax),
+            partitioned: false,
+            protected: None,
+        }
+    }
+
+    /// Creates a cookie with a percent encoded value, so any string can be stored
+    ///
+    /// Use `WebData::cookie_decoded` to read it back.
+    pub fn encoded(name: impl Into<String>, value: &str) -> Self {
+        Cookie::new(name, url_encode(value))
+    }
+
+    /// Creates a cookie with a signed value
+    ///
+    /// The value should contain only cookie octets, consider to percent encode it.
+    /// The signature covers the final name, including a prefix added later.
+    pub fn signed(name: impl Into<String>, value: &str, keys: &CookieKeys) -> Self {
+        let name = name.into();
+        let signed = keys.sign(&name, value);
+        Cookie {
+            protected: Some(Protected {
+                keys: keys.clone(),
+                plain: value.to_string(),
+                nonce: None,
+            }),
+            ..Cookie::new(name, signed)
+        }
+    }
+
+    /// Creates a cookie with an encrypted value
+    ///
+    /// `Err` is returned when no random source is available.
+    /// The encryption is bound to the final name, including a prefix added later.
+    pub fn encrypted(
+        name: impl Into<String>,
+        value: &str,
+        keys: &CookieKeys,
+    ) -> Result<Self, WebError> {
+        let name = name.into();
+        let nonce = random_nonce()?;
+        let encrypted = keys.seal(&name, value, &nonce);
+        Ok(Cookie {
+            protected: Some(Protected {
+                keys: keys.clone(),
+                plain: value.to_string(),
+                nonce: Some(nonce),
+            }),
+            ..Cookie::new(name, encrypted)
+        })
+    }
+
+    /// Creates a cookie which removes a previously set cookie with the same name, path and domain
+    pub fn removal(name: impl Into<String>) -> Self {
+        Cookie::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(SystemTime::UNIX_EPOCH)
+    }
+
+    /// Sets `Path`, `None` removes the attribute
+    pub fn path(mut self, path: Option<&str>) -> Self {
+        self.path = path.map(str::to_string);
+        self
+    }
+
+    pub fn domain(mut self, domain: Option<&str>) -> Self {
+        self.domain = domain.map(str::to_string);
+        self
+    }
+
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    pub fn expires(mut self, time: SystemTime) -> Self {
+        self.expires = Some(time);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Sets `SameSite`, `None` removes the attribute
+    pub fn same_site(mut self, same_site: Option<SameSite>) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Sets `Partitioned` (CHIPS), it requires `Secure`
+    pub fn partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    /// Adds the `__Host-` prefix to the name, which also requires `Secure`, `Path=/` and no `Domain`
+    pub fn host_prefixed(mut self) -> Self {
+        self.add_prefix("__Host-");
+        self.secure = true;
+        self.path = Some("/".to_string());
+        self.domain = None;
+        self
+    }
+
+    /// Adds the `__Secure-` prefix to the name, which also requires `Secure`
+    pub fn secure_prefixed(mut self) -> Self {
+        self.add_prefix("__Secure-");
+        self.secure = true;
+        self
+    }
+
+    /// Prefixes the name, and protects a protected value again for the new name
+    ///
+    /// A clone can keep the former value, so an encrypted value is sealed with a new nonce.
+    /// The value stays empty when no nonce can be obtained, and `validate` fails.
+    fn add_prefix(&mut self, prefix: &str) {
+        if self.name.starts_with(prefix) {
+            return;
+        }
+        self.name.insert_str(0, prefix);
+        if let Some(Protected { keys, plain, nonce }) = &mut self.protected {
+            self.value = match nonce {
+                Some(nonce) => match random_nonce() {
+                    Ok(fresh) => {
+                        *nonce = fresh;
+                        keys.seal(&self.name, plain, nonce)
+                    }
+                    Err(_) => String::new(),
+                },
+                None => keys.sign(&self.name, plain),
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Checks the cookie accordingly RFC 6265 and the prefix rules
+    pub fn validate(&self) -> Result<(), WebError> {
+        if self.name.is_empty() || !self.name.bytes().all(is_token_octet) {
+            return Err(cookie_error(
+                format! {"invalid cookie name '{}'", self.name},
+            ));
+        }
+        let value = match self.value.strip_prefix('"') {
+            Some(quoted) => quoted.strip_suffix('"').ok_or_else(|| {
+                cookie_error(format! {"unbalanced quote in the value of '{}'", self.name})
+            })?,
+            None => &self.value,
+        };
+        if !value.bytes().all(is_cookie_octet) {
+            return Err(cookie_error(format! {"invalid value of '{}'", self.name}));
+        }
+        if value.is_empty()
+            && self
+                .protected
+                .as_ref()
+                .is_some_and(|protected| protected.nonce.is_some())
+        {
+            return Err(cookie_error(
+                format! {"no random nonce to encrypt '{}'", self.name},
+            ));
+        }
+        for (attr, val) in [("Path", &self.path), ("Domain", &self.domain)] {
+            if let Some(val) = val
+                && val
+                    .bytes()
+                    .any(|b| b == b';' || b.is_ascii_control() || b > 0x7e)
+            {
+                return Err(cookie_error(format! {"invalid {attr} of '{}'", self.name}));
+            }
+        }
+        if !self.secure {
+            if self.name.starts_with("__Secure-") || self.name.starts_with("__Host-") {
+                return Err(cookie_error(
+                    format! {"the prefixed '{}' requires Secure", self.name},
+                ));
+            }
+            if self.same_site == Some(SameSite::None) {
+                return Err(cookie_error(
+                    format! {"SameSite=None of '{}' requires Secure", self.name},
+                ));
+            }
+            if self.partitioned {
+                return Err(cookie_error(
+                    format! {"Partitioned '{}' requires Secure", self.name},
+                ));
+            }
+        }
+        if self.name.starts_with("__Host-")
+            && (self.domain.is_some() || self.path.as_deref() != Some("/"))
+        {
+            return Err(cookie_error(
+                format! {"'{}' requires Path=/ and no Domain", self.name},
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the header as a tuple: (name and value) ready to use in `WebPage::get_extra`
+    ///
+    /// `Err` is returned when the cookie isn't valid.
+    pub fn header(&self) -> Result<(String, String), WebError> {
+        self.validate()?;
+        let mut res = format! {"{}={}", self.name, self.value};
+        if let Some(ref path) = self.path {
+            res.push_str(&format! {"; Path={path}"})
+        }
+        if let Some(ref domain) = self.domain {
+            res.push_str(&format! {"; Domain={domain}"})
+        }
+        if let Some(expires) = self.expires {
+            res.push_str(&format! {"; Expires={}", http_format_time(expires)})
+        }
+        if let Some(age) = self.max_age {
+            res.push_str(&format! {"; Max-Age={}", age.as_secs()})
+        }
+        if self.secure {
+            res.push_str("; Secure")
+        }
+        if self.http_only {
+            res.push_str("; HttpOnly")
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => res.push_str("; SameSite=Strict"),
+            Some(SameSite::Lax) => res.push_str("; SameSite=Lax"),
+            Some(SameSite::None) => res.push_str("; SameSite=None"),
+            None => (),
+        }
+        if self.partitioned {
+            res.push_str("; Partitioned")
+        }
+        Ok(("Set-Cookie".to_string(), res))
+    }
+}
+
+/// Creates a header deleting a cookie
+///
+/// The path and the domain have to be the same as used for setting the cookie.
+/// A `__Host-` cookie is always deleted with `Path=/` and without `Domain`, as it can only be set so.
+///
+/// # Examples
+/// ```
+/// let (set_op, val) = delete_cookie_header("sid", Some("/"), None);
+/// ```
+pub fn delete_cookie_header(
+    name: &str,
+    path: Option<&str>,
+    domain: Option<&str>,
+) -> (String, String) {
+    let mut res =
+        format! {"{name}=; Max-Age=0; Expires={}", http_format_time(SystemTime::UNIX_EPOCH)};
+    let (path, domain) = if name.starts_with("__Host-") {
+        (Some("/"), None)
+    } else {
+        (path, domain)
+    };
+    if let Some(path) = path {
+        res.push_str(&format! {"; Path={path}"})
+    }
+    if let Some(domain) = domain {
+        res.push_str(&format! {"; Domain={domain}"})
+    }
+    if name.starts_with("__Secure-") || name.starts_with("__Host-") {
+        res.push_str("; Secure")
+    }
+    ("Set-Cookie".to_string(), res)
+}
+
+fn cookie_error(reason: String) -> WebError {
+    WebError {
+        reason,
+        cause: None,
+    }
+}
+
+/// a token char of RFC 9110
+fn is_token_octet(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// a cookie-octet of RFC 6265
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+/// Parses a `Cookie` request header accordingly RFC 6265
+///
+/// Returns (name, value) pairs in the header order including duplicates. Whitespaces around
+/// names and values are trimmed, and surrounding quotes of values are removed.
+/// Pairs without `=` or with an empty name are skipped.
+///
+/// # Examples
+/// ```
+/// let cookies = parse_cookie_header(r#"sid=12; theme="dark"; sid=34"#);
+/// assert_eq!(cookies[1], ("theme".to_string(), "dark".to_string()));
+/// ```
+pub fn parse_cookie_header(header: &str) -> Vec<(String, String)> {
+    header
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .filter_map(|(name, val)| {
+            let name = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let val = val.trim();
+            let val = match val.strip_prefix('"').and_then(|val| val.strip_suffix('"')) {
+                Some(unquoted) => unquoted,
+                None => val,
+            };
+            Some((name.to_string(), val.to_string()))
+        })
+        .collect()
+}
+
+/// Decodes %XX sequences, unlike a URL component decoding, `+` stays as is
+pub(crate) fn percent_decode(val: &str) -> Option<String> {
+    let mut res = Vec::with_capacity(val.len());
+    let mut bytes = val.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let d1 = (bytes.next()? as char).to_digit(16)?;
+            let d2 = (bytes.next()? as char).to_digit(16)?;
+            res.push(((d1 << 4) + d2) as u8)
+        } else {
+            res.push(b)
+        }
+    }
+    String::from_utf8(res).ok()
+}
+
+/// A reason why a protected cookie was rejected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CookieError {
+    /// the value doesn't have a form of a protected value
+    Malformed,
+    /// the signature doesn't match to any key, the value was altered
+    BadSignature,
+    /// the value can't be decrypted by any key, it was altered
+    DecryptionFailed,
+}
+
+impl Error for CookieError {}
+
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CookieError::Malformed => write!(f, "malformed protected cookie"),
+            CookieError::BadSignature => write!(f, "cookie signature mismatch"),
+            CookieError::DecryptionFailed => write!(f, "cookie decryption failed"),
+        }
+    }
+}
+
+/// A kind of a cookie protection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protection {
+    /// the value is readable by a client, but can't be altered
+    Signed,
+    /// the value is hidden from a client and can't be altered
+    Encrypted,
+}
+
+/// Secret keys used for signing and encrypting cookies
+///
+/// The first key protects new cookies, retired keys are only used for a verification,
+/// so keys can be rotated without invalidating issued cookies.
+/// Signing uses HMAC-SHA256 and encryption uses ChaCha20-Poly1305, both bind a value to the cookie name.
+///
+/// # Examples
+/// ```
+/// let keys = CookieKeys::new(b"a new secret of 32 bytes or more.")?.retired(b"an old secret of 32 bytes or more")?;
+/// let (set_op, val) = Cookie::signed("user", "joe", &keys).header()?;
+/// // next request
+/// let data = WebData::with_config(&DataConfig {
+///     cookie_keys: Some(keys),
+///     protected_cookies: vec![("user".to_string(), Protection::Signed)],
+///     ..Default::default()
+/// });
+/// match data.cookie("user") {
+///     Some(user) => (),
+///     None => eprintln!{"{:?}", data.cookie_error("user")},
+/// }
+/// ```
+#[derive(Clone)]
+pub struct CookieKeys {
+    keys: Vec<Vec<u8>>,
+}
+
+impl fmt::Debug for CookieKeys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CookieKeys")
+            .field("retired", &(self.keys.len() - 1))
+            .finish_non_exhaustive()
+    }
+}
+
+impl CookieKeys {
+    /// Creates keys with the current secret, which has to have at least 32 random bytes
+    pub fn new(secret: &[u8]) -> Result<Self, WebError> {
+        Ok(CookieKeys {
+            keys: vec![checked_secret(secret)?],
+        })
+    }
+
+    /// Adds a retired secret still accepted for a verification, it has to have at least 32 bytes too
+    pub fn retired(mut self, secret: &[u8]) -> Result<Self, WebError> {
+        self.keys.push(checked_secret(secret)?);
+        Ok(self)
+    }
+
+    /// Returns the value with an appended signature
+    pub fn sign(&self, name: &str, value: &str) -> String {
+        format! {"{value}.{}", to_hex(&signature(&self.keys[0], name, value))}
+    }
+
+    /// Verifies a signed value and returns the original value
+    pub fn verify(&self, name: &str, signed: &str) -> Result<String, CookieError> {
+        let (value, sign) = signed.rsplit_once('.').ok_or(CookieError::Malformed)?;
+        let sign = from_hex(sign).ok_or(CookieError::Malformed)?;
+        if self
+            .keys
+            .iter()
+            .any(|key| constant_time_eq(&signature(key, name, value), &sign))
+        {
+            Ok(value.to_string())
+        } else {
+            Err(CookieError::BadSignature)
+        }
+    }
+
+    /// Encrypts the value and returns it as hex encoded nonce, ciphertext and tag
+    ///
+    /// `Err` is returned when no random nonce can be obtained.
+    pub fn encrypt(&self, name: &str, value: &str) -> Result<String, WebError> {
+        Ok(self.seal(name, value, &random_nonce()?))
+    }
+
+    fn seal(&self, name: &str, value: &str, nonce: &[u8; chacha::NONCE_LEN]) -> String {
+        let sealed = chacha::seal(
+            &hmac_sha256(&self.keys[0], ENCRYPT_LABEL),
+            nonce,
+            name.as_bytes(),
+            value.as_bytes(),
+        );
+        to_hex(nonce) + &to_hex(&sealed)
+    }
+
+    /// Decrypts a value encrypted by `encrypt`
+    pub fn decrypt(&self, name: &str, encrypted: &str) -> Result<String, CookieError> {
+        let data = from_hex(encrypted).ok_or(CookieError::Malformed)?;
+        if data.len() < chacha::NONCE_LEN + chacha::TAG_LEN {
+            return Err(CookieError::Malformed);
+        }
+        let (nonce, sealed) = data.split_at(chacha::NONCE_LEN);
+        let nonce = nonce.try_into().map_err(|_| CookieError::Malformed)?;
+        for key in &self.keys {
+            if let Ok(plain) = chacha::open(
+                &hmac_sha256(key, ENCRYPT_LABEL),
+                nonce,
+                name.as_bytes(),
+                sealed,
+            ) {
+                return String::from_utf8(plain).map_err(|_| CookieError::Malformed);
+            }
+        }
+        Err(CookieError::DecryptionFailed)
+    }
+
+    /// Unprotects a value accordingly the protection kind
+    pub fn unprotect(
+        &self,
+        name: &str,
+        value: &str,
+        protection: Protection,
+    ) -> Result<String, CookieError> {
+        match protection {
+            Protection::Signed => self.verify(name, value),
+            Protection::Encrypted => self.decrypt(name, value),
+        }
+    }
+}
+
+const SIGN_LABEL: &[u8] = b"simweb cookie signing";
+const MIN_SECRET_LEN: usize = 32;
+const ENCRYPT_LABEL: &[u8] = b"simweb cookie encryption";
+
+fn checked_secret(secret: &[u8]) -> Result<Vec<u8>, WebError> {
+    if secret.len() < MIN_SECRET_LEN {
+        return Err(cookie_error(
+            format! {"a cookie secret has to have at least {MIN_SECRET_LEN} bytes"},
+        ));
+    }
+    Ok(secret.to_vec())
+}
+
+fn random_nonce() -> Result<[u8; chacha::NONCE_LEN], WebError> {
+    let mut nonce = [0_u8; chacha::NONCE_LEN];
+    fill_random(&mut nonce).map_err(|err| WebError {
+        reason: "no random source".to_string(),
+        cause: Some(Box::new(err)),
+    })?;
+    Ok(nonce)
+}
+
+fn signature(secret: &[u8], name: &str, value: &str) -> [u8; 32] {
+    hmac_sha256(
+        &hmac_sha256(secret, SIGN_LABEL),
+        format! {"{name}={value}"}.as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"a secret of 32 bytes for testing";
+
+    #[test]
+    fn prefixed_protection() {
+        let keys = CookieKeys::new(SECRET).unwrap();
+        let signed = Cookie::signed("user", "joe", &keys).host_prefixed();
+        assert_eq!(signed.name(), "__Host-user");
+        assert_eq!(
+            keys.verify("__Host-user", signed.value()),
+            Ok("joe".to_string())
+        );
+        assert_eq!(
+            keys.verify("user", signed.value()),
+            Err(CookieError::BadSignature)
+        );
+        let encrypted = Cookie::encrypted("user", "joe", &keys)
+            .unwrap()
+            .secure_prefixed();
+        assert_eq!(
+            keys.decrypt("__Secure-user", encrypted.value()),
+            Ok("joe".to_string())
+        );
+        assert_eq!(
+            keys.decrypt("user", encrypted.value()),
+            Err(CookieError::DecryptionFailed)
+        );
+        let plain = Cookie::encrypted("user", "joe", &keys).unwrap();
+        let prefixed = plain.clone().host_prefixed();
+        let nonce_hex = chacha::NONCE_LEN * 2;
+        assert_ne!(plain.value()[..nonce_hex], prefixed.value()[..nonce_hex]);
+        assert_eq!(
+            keys.decrypt("__Host-user", prefixed.value()),
+            Ok("joe".to_string())
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let (name, val) = Cookie::new("id", "1")
+            .path(Some("/app"))
+            .domain(Some("example.com"))
+            .max_age(Duration::from_secs(60))
+            .expires(SystemTime::UNIX_EPOCH)
+            .http_only(false)
+            .same_site(Some(SameSite::None))
+            .partitioned(true)
+            .header()
+            .unwrap();
+        assert_eq!(name, "Set-Cookie");
+        assert_eq!(
+            val,
+            format! {"id=1; Path=/app; Domain=example.com; Expires={}; Max-Age=60; Secure; SameSite=None; Partitioned",
+            http_format_time(SystemTime::UNIX_EPOCH)}
+        );
+        let (_, val) = Cookie::new("id", "1")
+            .path(None)
+            .same_site(None)
+            .header()
+            .unwrap();
+        assert_eq!(val, "id=1; Secure; HttpOnly");
+        let (_, val) = Cookie::encoded("name", "a b;c").header().unwrap();
+        assert!(val.starts_with("name=a%20b%3bc;"));
+        let (_, val) = Cookie::removal("id").header().unwrap();
+        assert!(
+            val.starts_with(&format! {"id=; Path=/; Expires={}; Max-Age=0;",
+            http_format_time(SystemTime::UNIX_EPOCH)})
+        );
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Cookie::new("a b", "1").header().is_err());
+        assert!(Cookie::new("", "1").header().is_err());
+        assert!(Cookie::new("a", "x;y").header().is_err());
+        assert!(Cookie::new("a", "\"x").header().is_err());
+        assert!(Cookie::new("a", "\"x\"").header().is_ok());
+        assert!(Cookie::new("a", "1").path(Some("/a;b")).header().is_err());
+        let insecure = Cookie::new("a", "1").secure(false);
+        assert!(insecure.clone().header().is_ok());
+        assert!(
+            insecure
+                .clone()
+                .same_site(Some(SameSite::None))
+                .header()
+                .is_err()
+        );
+        assert!(insecure.clone().partitioned(true).header().is_err());
+        assert!(
+            Cookie::new("__Secure-a", "1")
+                .secure(false)
+                .header()
+                .is_err()
+        );
+        assert!(
+            Cookie::new("__Host-a", "1")
+                .path(Some("/app"))
+                .header()
+                .is_err()
+        );
+        assert!(
+            Cookie::new("__Host-a", "1")
+                .domain(Some("example.com"))
+                .header()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn prefixes() {
+        let cookie = Cookie::new("a", "1")
+            .secure(false)
+            .path(Some("/app"))
+            .domain(Some("example.com"))
+            .host_prefixed();
+        assert_eq!(
+            cookie.header().unwrap().1,
+            "__Host-a=1; Path=/; Secure; HttpOnly; SameSite=Lax"
+        );
+        let cookie = Cookie::new("a", "1")
+            .secure(false)
+            .secure_prefixed()
+            .secure_prefixed();
+        assert_eq!(cookie.name(), "__Secure-a");
+        assert!(cookie.header().is_ok());
+        let (_, val) = delete_cookie_header("__Host-csrf", Some("/app"), Some("example.com"));
+        assert!(val.ends_with("; Path=/; Secure"));
+        assert!(!val.contains("Domain"));
+        let (_, val) = delete_cookie_header("__Secure-id", None, Some("example.com"));
+        assert!(val.ends_with("; Domain=example.com; Secure"));
+        let (_, val) = delete_cookie_header("id", Some("/app"), None);
+        assert!(val.ends_with("; Path=/app"));
+    }
+
+    #[test]
+    fn parse_header() {
+        assert_eq!(
+            parse_cookie_header(r#" sid = 12 ; theme="dark"; empty=; =x; flag; sid=34; q="a"b""#),
+            [
+                ("sid".to_string(), "12".to_string()),
+                ("theme".to_string(), "dark".to_string()),
+                ("empty".to_string(), String::new()),
+                ("sid".to_string(), "34".to_string()),
+                ("q".to_string(), "a\"b".to_string()),
+            ]
+        );
+        assert!(parse_cookie_header("").is_empty());
+        assert_eq!(percent_decode("a%20b+c").as_deref(), Some("a b+c"));
+        assert_eq!(percent_decode("a%2"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn rotation() {
+        let old = CookieKeys::new(SECRET).unwrap();
+        let keys = CookieKeys::new(b"another secret of 32 bytes or more")
+            .unwrap()
+            .retired(SECRET)
+            .unwrap();
+        let value = old.sign("user", "joe");
+        assert_eq!(keys.verify("user", &value), Ok("joe".to_string()));
+        assert!(CookieKeys::new(b"short").is_err());
+        assert!(keys.retired(b"short").is_err());
+    }
+}
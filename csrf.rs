@@ -0,0 +1,55 @@
+use crate::{Cookie, Session, WebData, random_hex};
+use std::io;
+
+/// A name of the form field carrying a CSRF token
+pub const CSRF_FIELD: &str = "csrf_token";
+/// A name of the double-submit cookie, the `__Host-` prefix prevents a sibling subdomain
+/// from planting it, so the cookie is sent over HTTPS only
+pub const CSRF_COOKIE: &str = "__Host-csrf";
+const SESSION_KEY: &str = "_csrf";
+
+/// Generates a new random CSRF token
+pub fn new_csrf_token() -> io::Result<String> {
+    random_hex(32)
+}
+
+/// Returns a CSRF token bound to the session, the token is created when the session has none
+///
+/// The session has to be saved to keep a new token.
+pub fn session_csrf_token(session: &mut Session) -> io::Result<String> {
+    if let Some(token) = session.get(SESSION_KEY) {
+        return Ok(token.to_string());
+    }
+    let token = new_csrf_token()?;
+    session.set(SESSION_KEY, &token);
+    Ok(token)
+}
+
+/// Returns a CSRF token of the double-submit cookie
+///
+/// When the request has no the cookie, a new token is created, and `Set-Cookie` header is returned
+/// as the second element of the tuple. The header should be added to the response.
+pub fn double_submit_token(data: &WebData) -> io::Result<(String, Option<(String, String)>)> {
+    if let Some(token) = data.cookie(CSRF_COOKIE)
+        && is_token(&token)
+    {
+        return Ok((token, None));
+    }
+    let token = new_csrf_token()?;
+    let header = Cookie::new(CSRF_COOKIE, &token)
+        .host_prefixed()
+        .header()
+        .map_err(|err| io::Error::other(err.reason))?;
+    Ok((token, Some(header)))
+}
+
+/// Returns a hidden form field with the token
+///
+/// `WebPage::show` adds it to the template as `${csrf_field}` when `WebPage::csrf_token` returns a token.
+pub fn csrf_field(token: &str) -> String {
+    format! {r#"<input type="hidden" name="{CSRF_FIELD}" value="{}">"#, crate::html_encode(token)}
+}
+
+fn is_token(token: &str) -> bool {
+    token.len() == 64 && token.bytes().all(|b| b.is_ascii_hexdigit())
+}
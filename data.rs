@@ -1,24 +1,74 @@
-use crate::WebError;
-use crate::mpart::Storage;
+use crate::charset::{Charset, DecodePolicy};
+use crate::checksum::{ChecksumKind, ChecksumReader};
+use crate::cookie::{CookieError, CookieKeys, Protection, parse_cookie_header, percent_decode};
+use crate::csrf::{CSRF_COOKIE, CSRF_FIELD};
+use crate::form::ParamSource;
+use crate::hash::constant_time_eq;
+use crate::media::{MediaType, negotiate};
+use crate::nested::ParamTree;
+use crate::progress::{ProgressFile, ProgressReader, UPLOAD_ID_PARAM};
+use crate::storage::{DirStorage, UploadStorage};
+use crate::upload::{CollisionPolicy, UploadedFile};
+use crate::validate::{FieldError, FieldErrorKind};
+use crate::{WebError, url_comp_decode_bytes};
 use simtime::{get_datetime, seconds_from_epoch};
 use std::{
-    collections::HashMap,
+    collections::HashSet,
     env,
     error::Error,
-    fs::{self, File},
-    io::{self, ErrorKind, Read, Write},
+    io::{self, Read},
     path::{MAIN_SEPARATOR, PathBuf},
+    str::FromStr,
     time::SystemTime,
 };
 
+/// Settings of request processing used by `WebData::with_config`
+#[derive(Debug, Clone, Default)]
+pub struct DataConfig {
+    /// a directory of uploaded files, `ATTACH_DIR` or the current directory is used when it's `None`,
+    /// it's ignored by `WebData::with_storage`
+    pub upload_dir: Option<PathBuf>,
+    /// what to do when an uploaded file name is taken in the upload directory
+    pub collision: CollisionPolicy,
+    /// uploaded files which weren't claimed by a handler are removed when `WebData` is dropped
+    pub remove_unclaimed: bool,
+    /// digests of uploaded files calculated while they are stored
+    pub checksums: Vec<ChecksumKind>,
+    /// a charset of parameters when neither a part nor the `_charset_` field tells it
+    pub default_charset: Charset,
+    /// what to do with a parameter which can't be decoded by its charset
+    pub decode_policy: DecodePolicy,
+    /// a directory of `ProgressFile` written while a multipart body is received,
+    /// when the query string has `upload_id`
+    pub progress_dir: Option<PathBuf>,
+    /// a limit of a text field of a multipart body in bytes, a longer field is skipped,
+    /// `MAX_FIELD_LEN` is used when it's `None`
+    pub max_field_len: Option<usize>,
+    /// keys verifying and unprotecting `protected_cookies`
+    pub cookie_keys: Option<CookieKeys>,
+    /// names of signed and encrypted cookies as the browser sends them, including a prefix,
+    /// they are rejected when there are no `cookie_keys`
+    pub protected_cookies: Vec<(String, Protection)>,
+}
+
 #[derive(Debug)]
 pub struct WebData {
-    params: HashMap<String, String>, // &str or (String, Option<Vec<String>>)
-    params_dup: HashMap<String, Vec<String>>,
-    cookies: HashMap<String, String>,
+    query_params: Vec<(String, String)>,
+    body_params: Vec<(String, String)>,
+    cookies: Vec<(String, String)>,
+    cookie_errors: Vec<(String, CookieError)>,
+    uploads: Vec<UploadedFile>,
+    /// a storage of the uploads, it removes unclaimed ones
+    storage: Box<dyn UploadStorage>,
     pub query: Option<String>,
 }
 
+/// a default limit of a text field of a multipart body
+pub const MAX_FIELD_LEN: usize = 1024 * 1024;
+
+/// a field of HTML forms telling a charset of the submission
+const CHARSET_FIELD: &str = "_charset_";
+
 pub const HTTP_DAYS_OF_WEEK: &[&str] = &["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
 
 pub const HTTP_MONTH: &[&str] = &[
@@ -32,44 +82,59 @@ impl Default for WebData {
     }
 }
 
+impl Drop for WebData {
+    fn drop(&mut self) {
+        for upload in self.uploads.iter().filter(|upload| upload.is_unclaimed()) {
+            if let Err(err) = self.storage.remove(&upload.path) {
+                eprintln! {"error: unclaimed upload {} not removed <= {err}", upload.path.display()}
+            }
+        }
+    }
+}
+
 impl WebData {
     /// Creates WebData object which can be a sigleton
     /// as a part of its creation, it processes web parameters
     /// as from a query string as from the data of POST request in type: application/x-www-form-urlencoded
     pub fn new() -> Self {
+        Self::with_config(&DataConfig::default())
+    }
+
+    /// Creates WebData like `new`, using the settings for uploaded files
+    pub fn with_config(config: &DataConfig) -> Self {
+        let dir = config.upload_dir.clone().unwrap_or_else(get_attachment_dir);
+        Self::with_storage(config, DirStorage::new(dir, config.collision))
+    }
+
+    /// Creates WebData like `with_config`, uploaded files are stored by the storage
+    ///
+    /// # Examples
+    /// ```
+    /// let user_dir = Path::new("/var/www/uploads").join(&user);
+    /// let data = WebData::with_storage(&DataConfig::default(), DirStorage::new(user_dir, CollisionPolicy::Random));
+    /// ```
+    pub fn with_storage(config: &DataConfig, storage: impl UploadStorage + 'static) -> Self {
         let mut res = WebData {
-            params: HashMap::new(),
-            params_dup: HashMap::new(),
-            cookies: HashMap::new(),
+            query_params: Vec::new(),
+            body_params: Vec::new(),
+            cookies: Vec::new(),
+            cookie_errors: Vec::new(),
+            uploads: Vec::new(),
+            storage: Box::new(storage),
             query: None,
         };
         if let Ok(query) = env::var("QUERY_STRING") {
-            let parts = query.split("&");
-            for part in parts {
-                if let Some((key, val)) = part.split_once("=")
-                    && let Some(key) = res.url_comp_decode(key)
-                    && let Some(val) = res.url_comp_decode(val)
-                    && let Some(prev) = res.params.insert(key.clone(), val)
-                {
-                    let others = res.params_dup.get_mut(&key);
-                    match others {
-                        None => {
-                            let params = vec![prev];
-                            res.params_dup.insert(key, params);
-                        }
-                        Some(others) => others.push(prev),
-                    }
-                };
-            }
+            parse_urlencoded(
+                &query,
+                config.default_charset,
+                config.decode_policy,
+                &mut res.query_params,
+            );
+            res.query = Some(query)
         }
         if let Ok(header_cookies) = env::var("HTTP_COOKIE") {
-            let parts = header_cookies.split(";");
-            for part in parts {
-                if let Some(keyval) = part.split_once('=') {
-                    res.cookies
-                        .insert(keyval.0.trim().to_string(), keyval.1.to_string());
-                }
-            }
+            res.cookies = parse_cookie_header(&header_cookies);
+            res.verify_cookies(config.cookie_keys.as_ref(), &config.protected_cookies)
         } else {
             // eprintln!{"No cookie header"}
         }
@@ -86,45 +151,55 @@ impl WebData {
             let mut user_input = String::new();
             let stdin = io::stdin();
             if let Ok(content_type) = env::var("CONTENT_TYPE") {
-                match content_type.as_str() {
-                    "application/x-www-form-urlencoded" => {
+                match MediaType::parse(&content_type) {
+                    Ok(media) if media.is("application/x-www-form-urlencoded") => {
                         if let Ok(_ok) = stdin.read_line(&mut user_input) {
-                            let parts = user_input.split("&");
-                            for part in parts {
-                                if let Some((key, val)) = part.split_once("=")
-                                    && let Some(key) = res.url_comp_decode(key)
-                                    && let Some(val) = res.url_comp_decode(val)
-                                    && let Some(prev) = res.params.insert(key.clone(), val)
-                                {
-                                    let others = res.params_dup.get_mut(&key);
-                                    match others {
-                                        None => {
-                                            let params = vec![prev];
-                                            res.params_dup.insert(key, params);
-                                        }
-                                        Some(others) => others.push(prev),
-                                    }
-                                };
-                            }
+                            let charset = media
+                                .charset()
+                                .and_then(Charset::from_label)
+                                .unwrap_or(config.default_charset);
+                            parse_urlencoded(
+                                &user_input,
+                                charset,
+                                config.decode_policy,
+                                &mut res.body_params,
+                            )
                         }
                         // sink reminded if any
                     }
-                    _ if content_type.starts_with("multipart/form-data;") => {
-                        match parse_multipart(
-                            &content_type,
-                            stdin,
-                            length as usize,
-                            &mut res.params,
-                            &mut res.params_dup,
-                        ) {
-                            Ok(()) => (),
-                            Err(err) => {
-                                eprintln! {"error: parse multi parts failed <= {err}"}
+                    Ok(media) if media.is("multipart/form-data") => {
+                        let mut input = stdin.lock().take(length);
+                        let sink = match (&config.progress_dir, res.param(UPLOAD_ID_PARAM)) {
+                            (Some(dir), Some(id)) => ProgressFile::new(dir, &id)
+                                .inspect_err(|err| eprintln! {"error: no upload progress <= {err}"})
+                                .ok(),
+                            _ => None,
+                        };
+                        let mut parse = |input: &mut dyn Read| {
+                            parse_multipart(
+                                &media,
+                                input,
+                                config,
+                                res.storage.as_mut(),
+                                &mut res.body_params,
+                                &mut res.uploads,
+                            )
+                        };
+                        let parsed = match sink {
+                            Some(sink) => {
+                                let mut input = ProgressReader::new(input, Some(length), sink);
+                                // the body can be read to the end and still be malformed
+                                parse(&mut input).inspect_err(|err| input.fail(&err.to_string()))
                             }
+                            None => parse(&mut input),
+                        };
+                        if let Err(err) = parsed {
+                            eprintln! {"error: parse multi parts failed <= {err}"}
                         }
                         // sink reminded if any
                     }
-                    _ => (), // sink reminded if any
+                    Ok(_) => (), // sink reminded if any
+                    Err(err) => eprintln! {"error: {err}"},
                 }
             } else {
                 // read by end
@@ -135,45 +210,255 @@ impl WebData {
 
     /// Returns a parameter value with given name.
     ///
-    /// If there are more than one parameter with given name, only the first one is returned.
+    /// If there are more than one parameter with given name, the last one is returned,
+    /// so a parameter of the request body overrides a query string parameter of the same name,
+    /// and a checkbox after a hidden field of the same name overrides it.
     /// If there are no parameters with such name, then `None` is returned. A parameter value can be an empty string.
     ///
     /// The returned value is always cloned.
     pub fn param(&self, key: impl AsRef<str>) -> Option<String> {
-        self.params.get(key.as_ref()).cloned() // probably better to return as Option<&String> without using clone
+        let key = key.as_ref();
+        self.all_params()
+            .filter(|(name, _)| *name == key)
+            .last()
+            .map(|(_, val)| val.to_string())
+    }
+
+    /// Returns a parameter value parsed to the requested type.
+    ///
+    /// The value is trimmed before parsing. `Ok(None)` is returned when there is no parameter
+    /// with such name or its value is empty, and `Err` when the value can't be parsed.
+    ///
+    /// # Examples
+    /// ```
+    /// let page: Option<u32> = WebData::new().param_as("page")?;
+    /// ```
+    pub fn param_as<T: FromStr>(&self, key: impl AsRef<str>) -> Result<Option<T>, FieldError> {
+        let key = key.as_ref();
+        match self.param(key).as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(val) => val.parse::<T>().map(Some).map_err(|_| {
+                FieldError::new(
+                    key,
+                    FieldErrorKind::Parse,
+                    "the value has an invalid format",
+                )
+            }),
+        }
+    }
+
+    /// Returns a parsed parameter value, or `Err` when the parameter is absent, empty or can't be parsed.
+    pub fn param_required<T: FromStr>(&self, key: impl AsRef<str>) -> Result<T, FieldError> {
+        let key = key.as_ref();
+        self.param_as(key)?
+            .ok_or_else(|| FieldError::new(key, FieldErrorKind::Missing, "a value is required"))
+    }
+
+    /// Returns a parsed parameter value, or the default value when the parameter is absent or empty.
+    ///
+    /// A value which can't be parsed is still reported as `Err`.
+    pub fn param_or<T: FromStr>(&self, key: impl AsRef<str>, default: T) -> Result<T, FieldError> {
+        Ok(self.param_as(key)?.unwrap_or(default))
     }
 
     /// Returns a parameter value with given name as a Vec, regardless if there is only one value.
     ///
-    /// There is no the particular order of parameters preserved.
+    /// The values are in the order they were submitted, query string parameters go first.
     /// If there are no parameters with such name, then `None` is returned.
     /// The returned Vec will always have at least one element which can be an empty `String`.
     ///
     /// The returned values are always cloned.
     pub fn params(&self, key: impl AsRef<str>) -> Option<Vec<String>> {
         let key = key.as_ref();
-        match self.params.get(key) {
-            None => None,
-            Some(val) => {
-                let mut res = vec![val.clone()];
-                match self.params_dup.get(key).cloned() {
-                    None => Some(res),
-                    Some(vec) => {
-                        res.extend(vec);
-                        Some(res)
-                    }
-                }
-            }
+        let res: Vec<_> = self
+            .all_params()
+            .filter(|(name, _)| *name == key)
+            .map(|(_, val)| val.to_string())
+            .collect();
+        if res.is_empty() { None } else { Some(res) }
+    }
+
+    /// Returns an iterator over all parameters as (name, value) pairs in the order they were submitted.
+    ///
+    /// Query string parameters go first, then parameters of the request body.
+    /// A name can appear more than once.
+    pub fn all_params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.query_params().chain(self.body_params())
+    }
+
+    /// Returns an iterator over parameters of the query string in their order
+    pub fn query_params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.query_params
+            .iter()
+            .map(|(name, val)| (name.as_str(), val.as_str()))
+    }
+
+    /// Returns an iterator over parameters of the request body in their order
+    ///
+    /// Uploaded files are represented by their stored paths.
+    pub fn body_params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.body_params
+            .iter()
+            .map(|(name, val)| (name.as_str(), val.as_str()))
+    }
+
+    /// Returns the first file uploaded in the form field
+    pub fn upload(&self, field: impl AsRef<str>) -> Option<&UploadedFile> {
+        let field = field.as_ref();
+        self.uploads.iter().find(|upload| upload.field == field)
+    }
+
+    /// Returns all files uploaded in the form field in the order they were submitted
+    ///
+    /// An empty Vec is returned when no file was uploaded.
+    pub fn uploads(&self, field: impl AsRef<str>) -> Vec<&UploadedFile> {
+        let field = field.as_ref();
+        self.uploads
+            .iter()
+            .filter(|upload| upload.field == field)
+            .collect()
+    }
+
+    /// Returns files uploaded in the form field, so they can be moved or kept
+    pub fn uploads_mut(
+        &mut self,
+        field: impl AsRef<str>,
+    ) -> impl Iterator<Item = &mut UploadedFile> {
+        let field = field.as_ref().to_string();
+        self.uploads
+            .iter_mut()
+            .filter(move |upload| upload.field == field)
+    }
+
+    /// Returns all uploaded files in the order they were submitted
+    pub fn all_uploads(&self) -> &[UploadedFile] {
+        &self.uploads
+    }
+
+    /// Returns names of all parameters without repetitions in the order of the first appearance
+    pub fn param_names(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        self.all_params()
+            .map(|(name, _)| name)
+            .filter(|name| seen.insert(*name))
+            .collect()
+    }
+
+    /// Returns all parameters as a tree built using the bracket notation of names.
+    ///
+    /// For example, `user[address][city]` is available as `param_tree().path(&["user", "address", "city"])`,
+    /// and `rows[3][qty]` as `param_tree().path(&["rows", "3", "qty"])`. See `ParamTree` for details.
+    pub fn param_tree(&self) -> ParamTree {
+        ParamTree::from_pairs(self.all_params())
+    }
+
+    /// Returns all values of a parameter taken from the given source.
+    ///
+    /// An empty Vec is returned when there are no such values. It's used by `FromWebData` implementations.
+    pub fn values_from(&self, source: ParamSource, key: impl AsRef<str>) -> Vec<String> {
+        let key = key.as_ref();
+        match source {
+            ParamSource::Param => self.params(key).unwrap_or_default(),
+            ParamSource::Query => self
+                .query_params()
+                .filter(|(name, _)| *name == key)
+                .map(|(_, val)| val.to_string())
+                .collect(),
+            ParamSource::Body => self
+                .body_params()
+                .filter(|(name, _)| *name == key)
+                .map(|(_, val)| val.to_string())
+                .collect(),
+            ParamSource::Cookie => self.cookie(key).into_iter().collect(),
+            ParamSource::Path(idx) => self
+                .path_info()
+                .split('/')
+                .filter(|seg| !seg.is_empty())
+                .nth(idx)
+                .map(|seg| vec![seg.to_string()])
+                .unwrap_or_default(),
         }
     }
 
     /// Returns a cookie value with given name.
     ///
     /// If there is no cookie with such name, then `None` is returned. A cookie value can be an empty string.
+    /// When a browser sends several cookies with the same name, the first one is returned,
+    /// generally it's the cookie with the longest path. Surrounding quotes of the value are removed.
+    /// A cookie of `DataConfig::protected_cookies` is returned only when it was verified, and its value
+    /// is the original one.
     ///
     /// The returned value is always cloned.
     pub fn cookie(&self, key: impl AsRef<str>) -> Option<String> {
-        self.cookies.get(key.as_ref()).cloned() // probably better to return as Option<&String> without using clone
+        let key = key.as_ref();
+        self.cookies()
+            .find(|(name, _)| *name == key)
+            .map(|(_, val)| val.to_string())
+    }
+
+    /// Returns a cookie value with given name decoded from the percent encoding.
+    ///
+    /// `None` is also returned when the value can't be decoded.
+    pub fn cookie_decoded(&self, key: impl AsRef<str>) -> Option<String> {
+        percent_decode(&self.cookie(key)?)
+    }
+
+    /// Returns values of all cookies with given name in the order the browser sent them.
+    ///
+    /// Cookies with the same name can come from different paths or domains.
+    pub fn cookie_all(&self, key: impl AsRef<str>) -> Vec<String> {
+        let key = key.as_ref();
+        self.cookies()
+            .filter(|(name, _)| *name == key)
+            .map(|(_, val)| val.to_string())
+            .collect()
+    }
+
+    /// Verifies and unprotects signed and encrypted cookies
+    ///
+    /// Values of the listed cookies are replaced by their original values, so `cookie` returns them as usual.
+    /// A cookie failed the verification is removed, and the reason is available from `cookie_error`.
+    fn verify_cookies(&mut self, keys: Option<&CookieKeys>, names: &[(String, Protection)]) {
+        let mut errors = Vec::new();
+        self.cookies.retain_mut(|(name, val)| {
+            let Some((_, protection)) = names.iter().find(|(key, _)| key == name) else {
+                return true;
+            };
+            let plain = match (keys, protection) {
+                (Some(keys), _) => keys.unprotect(name, val, *protection),
+                (None, Protection::Signed) => Err(CookieError::BadSignature),
+                (None, Protection::Encrypted) => Err(CookieError::DecryptionFailed),
+            };
+            match plain {
+                Ok(plain) => {
+                    *val = plain;
+                    true
+                }
+                Err(err) => {
+                    errors.push((name.clone(), err));
+                    false
+                }
+            }
+        });
+        self.cookie_errors.extend(errors)
+    }
+
+    /// Returns a reason why a cookie of `DataConfig::protected_cookies` with given name was rejected
+    ///
+    /// `None` means the cookie wasn't rejected, although it can be absent.
+    pub fn cookie_error(&self, key: impl AsRef<str>) -> Option<CookieError> {
+        let key = key.as_ref();
+        self.cookie_errors
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, err)| *err)
+    }
+
+    /// Returns an iterator over all cookies as (name, value) pairs in the order the browser sent them
+    pub fn cookies(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies
+            .iter()
+            .map(|(name, val)| (name.as_str(), val.as_str()))
     }
 
     /// Returns the path info.
@@ -184,147 +469,246 @@ impl WebData {
         env::var("PATH_INFO").unwrap_or_default()
     }
 
+    /// Chooses the best of available media types for `Accept` of the request
+    ///
+    /// The first type is returned when the request has no `Accept`, and `None` when nothing is acceptable.
+    ///
+    /// # Examples
+    /// ```
+    /// match data.preferred_type(&["text/html", "application/json"]) {
+    ///     Some("application/json") => print_json(),
+    ///     Some(_) => print_html(),
+    ///     None => print_status(406),
+    /// }
+    /// ```
+    pub fn preferred_type<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        negotiate(&env::var("HTTP_ACCEPT").unwrap_or_default(), available)
+    }
+
+    /// Returns the request method as GET or POST.
+    ///
+    /// An empty `String` is returned when the method is unknown.
+    pub fn method(&self) -> String {
+        env::var("REQUEST_METHOD").unwrap_or_default()
+    }
+
+    /// Verifies a CSRF token of a request using an unsafe method as POST, PUT, PATCH or DELETE
+    ///
+    /// The token is taken from the form field `csrf_token` of the request body or the header `X-CSRF-Token`.
+    /// A token of the query string is ignored, since URLs leak through `Referer` and logs.
+    /// Requests of safe methods as GET and HEAD always pass.
+    ///
+    /// # Examples
+    /// ```
+    /// fn csrf_verify(&self) -> Result<(), Box<dyn Error>> {
+    ///     Ok(self.data.verify_csrf(&session_csrf_token(&mut self.session.borrow_mut())?)?)
+    /// }
+    /// ```
+    pub fn verify_csrf(&self, expected: &str) -> Result<(), WebError> {
+        if matches!(
+            self.method().as_str(),
+            "GET" | "HEAD" | "OPTIONS" | "TRACE" | ""
+        ) {
+            return Ok(());
+        }
+        let token = self
+            .body_params()
+            .filter(|(name, _)| *name == CSRF_FIELD)
+            .last()
+            .map(|(_, val)| val.to_string())
+            .or_else(|| env::var("HTTP_X_CSRF_TOKEN").ok());
+        match token {
+            Some(token)
+                if !expected.is_empty()
+                    && constant_time_eq(token.as_bytes(), expected.as_bytes()) =>
+            {
+                Ok(())
+            }
+            Some(_) => Err(WebError {
+                reason: "CSRF token mismatch".to_string(),
+                cause: None,
+            }),
+            None => Err(WebError {
+                reason: "no CSRF token".to_string(),
+                cause: None,
+            }),
+        }
+    }
+
+    /// Verifies a CSRF token against the double-submit cookie
+    pub fn verify_csrf_cookie(&self) -> Result<(), WebError> {
+        self.verify_csrf(&self.cookie(CSRF_COOKIE).unwrap_or_default())
+    }
+
     /// Decodes URL component.
     ///
-    /// If a decoding impossible, the it returns `None`.
+    /// If a decoding impossible, the it returns `None`. A not encoded non ASCII character is kept as it is.
     ///
     /// A new string is always created regardless if an actual decoding happened.
     pub fn url_comp_decode(&self, comp: &str) -> Option<String> {
-        let mut res = Vec::with_capacity(256);
-
-        let mut chars = comp.chars();
-        while let Some(c) = chars.next() {
-            match c {
-                '%' => {
-                    let d1 = chars.next()?.to_digit(16)?;
-                    let d2 = chars.next()?.to_digit(16)?;
-                    res.push(((d1 << 4) + d2) as u8)
-                }
-                '+' => res.push(b' '),
-                _ => res.push(if c.is_ascii() { c as u8 } else { return None }),
-            }
+        crate::url_comp_decode(comp)
+    }
+}
+
+/// Parses `name=value` pairs, the charset can be changed by the `_charset_` field
+fn parse_urlencoded(
+    input: &str,
+    charset: Charset,
+    policy: DecodePolicy,
+    res: &mut Vec<(String, String)>,
+) {
+    let pairs: Vec<_> = input
+        .split('&')
+        .filter_map(|part| {
+            let (key, val) = part.split_once('=')?;
+            Some((url_comp_decode_bytes(key)?, url_comp_decode_bytes(val)?))
+        })
+        .collect();
+    let charset = form_charset(
+        pairs
+            .iter()
+            .map(|(key, val)| (key.as_slice(), val.as_slice())),
+    )
+    .unwrap_or(charset);
+    for (key, val) in pairs {
+        match (charset.decode(&key, policy), charset.decode(&val, policy)) {
+            (Ok(key), Ok(val)) => res.push((key, val)),
+            (Err(err), _) | (_, Err(err)) => eprintln! {"error: a parameter skipped <= {err}"},
         }
-        String::from_utf8(res).ok()
     }
 }
 
-use crate::mpart::MPart;
-// TODO make a method with self
+/// Returns a charset given by the `_charset_` field of HTML forms
+fn form_charset<'a>(mut fields: impl Iterator<Item = (&'a [u8], &'a [u8])>) -> Option<Charset> {
+    fields
+        .find(|(name, _)| *name == CHARSET_FIELD.as_bytes())
+        .and_then(|(_, val)| Charset::from_label(std::str::from_utf8(val).ok()?))
+}
+
+use crate::mpart::MultipartReader;
+
 fn parse_multipart(
-    content_type: &str,
-    mut stdin: io::Stdin,
-    length: usize,
-    res: &mut HashMap<String, String>,
-    res_dup: &mut HashMap<String, Vec<String>>,
+    media: &MediaType,
+    input: &mut dyn Read,
+    config: &DataConfig,
+    storage: &mut dyn UploadStorage,
+    res: &mut Vec<(String, String)>,
+    uploads: &mut Vec<UploadedFile>,
 ) -> Result<(), Box<dyn Error>> {
-    let Some((_, boundary)) = content_type.split_once("; boundary=") else {
+    let Some(boundary) = media.boundary().filter(|boundary| !boundary.is_empty()) else {
         return Err(Box::new(WebError {
             reason: "No boundary".to_string(),
             cause: None,
         }));
     };
-    let parts = MPart::from(&mut stdin, boundary.as_bytes());
-    let mut consumed = 0_usize;
-
-    for part in parts {
-        // eprintln!{"part {:?} / {:?} / {}",part.content_type, part.content_filename, &part.total_read_ammount}
-
-        let insert = |val| {
-            if let Some(prev) = res.insert(
-                part.content_name.clone(),
-                val, //String::from_utf8(part.content.clone()).unwrap()
-            ) {
-                let others = res_dup.get_mut(&part.content_name);
-                match others {
-                    None => {
-                        let params = vec![prev];
-                        res_dup.insert(part.content_name, params);
-                    }
-                    Some(others) => others.push(prev),
-                }
-            };
-        };
-
-        consumed = part.total_read_ammount;
-        match part.content_type {
-            None => insert(match part.content {
-                Storage::Mem(content) => String::from_utf8(content)?,
-                Storage::Disk(path) => fs::read_to_string(path)?,
-                _ => String::new(),
-            }),
-            // TODO apply any encoding if specified
-            Some(content_type)
-                if part.content_filename.is_none() && content_type.starts_with("text/") =>
-            {
-                let file_content;
-                insert(iso_8859_1_to_string(match &part.content {
-                    Storage::Mem(content) => content,
-                    Storage::Disk(path) => {
-                        file_content = fs::read(path)?; // can be too big for memory
-                        &file_content
-                    }
-                    _ => &[],
-                }))
+    // text fields are decoded at the end, since `_charset_` can go after them,
+    // the position in the result, the content, a charset of the part
+    let mut texts: Vec<(usize, Vec<u8>, Option<Charset>)> = Vec::new();
+    // fields read before an error are decoded anyway, so no empty placeholder is left
+    let read = read_parts(boundary, input, config, storage, res, uploads, &mut texts);
+    let form_charset = form_charset(
+        texts
+            .iter()
+            .map(|(pos, content, _)| (res[*pos].0.as_bytes(), content.as_slice())),
+    )
+    .unwrap_or(config.default_charset);
+    let mut malformed = Vec::new();
+    for (pos, content, charset) in texts {
+        match charset
+            .unwrap_or(form_charset)
+            .decode(&content, config.decode_policy)
+        {
+            Ok(val) => res[pos].1 = val,
+            Err(err) => {
+                eprintln! {"error: the field {} skipped <= {err}", res[pos].0};
+                malformed.push(pos)
             }
-            _ => match part.content_filename {
-                Some(content_filename) => {
-                    let mut file_name = get_attachment_dir();
-                    file_name.push(content_filename);
-                    match write_to_file(&part.content, file_name.to_str().unwrap()) {
-                        Ok(_) => {
-                            //eprintln!("File written successfully!");
-                            insert(file_name.to_str().unwrap().to_string());
-                        }
-                        Err(e) => eprintln!("Failed to write file: {}", e),
-                    };
-                }
-                _ => eprintln! {"can't save the field, since no file name"},
-            },
-        };
-    }
-    if length != consumed {
-        if length > consumed {
-            eprintln!("rest read {} {length} {consumed}", length - consumed);
-            io::copy(
-                &mut stdin.take((length - consumed) as u64),
-                &mut std::io::sink(),
-            )?;
         }
-        Err(Box::new(WebError {
-            reason: format!("Size mismatch, len: {length} < consumed: {consumed}"),
-            cause: None,
-        }))
-    } else {
-        Ok(())
     }
+    for pos in malformed.into_iter().rev() {
+        res.remove(pos);
+    }
+    Ok(read?)
 }
 
-fn write_to_file(data: &Storage, file_path: &str) -> std::io::Result<()> {
-    match data {
-        Storage::Mem(data) => {
-            File::create(file_path)?.write_all(data)?;
-        }
-        Storage::Disk(from_path) => {
-            if let Err(err) = fs::rename(from_path, file_path) {
-                if err.kind() == ErrorKind::CrossesDevices {
-                    match fs::copy(from_path, file_path) {
-                        Ok(_) => {
-                            let _ = fs::remove_file(from_path);
-                        }
-                        Err(err) => return Err(err),
+/// Reads parts storing files, text fields are put in the result as placeholders
+fn read_parts(
+    boundary: &str,
+    input: &mut dyn Read,
+    config: &DataConfig,
+    storage: &mut dyn UploadStorage,
+    res: &mut Vec<(String, String)>,
+    uploads: &mut Vec<UploadedFile>,
+    texts: &mut Vec<(usize, Vec<u8>, Option<Charset>)>,
+) -> io::Result<()> {
+    let mut parts = MultipartReader::new(&mut *input, boundary.as_bytes());
+    while let Some(mut part) = parts.next_part()? {
+        let headers = part.headers();
+        let name = headers.name.clone();
+        // a transfer encoding is decoded by the part reader
+        let part_type = headers
+            .content_type
+            .as_ref()
+            .map(|content_type| MediaType::parse(content_type));
+        let is_text = part_type
+            .as_ref()
+            .is_none_or(|media| media.as_ref().is_ok_and(|media| media.main == "text"));
+        match headers.filename.clone() {
+            None if is_text => {
+                let charset = part_type
+                    .and_then(Result::ok)
+                    .and_then(|media| Charset::from_label(media.charset()?));
+                let limit = config.max_field_len.unwrap_or(MAX_FIELD_LEN);
+                let headers = headers.clone();
+                // only digests of the headers are needed
+                let mut body = ChecksumReader::new(&mut part, &[], &headers);
+                let mut content = Vec::new();
+                // the rest of a long field is skipped by the next part
+                (&mut body)
+                    .take(limit as u64 + 1)
+                    .read_to_end(&mut content)?;
+                if content.len() > limit {
+                    eprintln! {"error: the field {name} skipped <= longer than {limit} bytes"};
+                    continue;
+                }
+                if let Err(err) = body.finish().verify(&headers) {
+                    eprintln! {"error: the field {name} skipped <= {}", err.reason};
+                    continue;
+                }
+                texts.push((res.len(), content, charset));
+                res.push((name, String::new()))
+            }
+            // a file wasn't selected
+            Some(content_filename) if content_filename.is_empty() => (),
+            Some(content_filename) => {
+                let headers = headers.clone();
+                match UploadedFile::store(&headers, &mut part, storage, config) {
+                    Ok(upload) => {
+                        res.push((name, upload.path.to_string_lossy().to_string()));
+                        uploads.push(upload)
                     }
-                } else {
-                    return Err(err);
+                    Err(e) => eprintln!("Failed to write file {content_filename}: {e}"),
                 }
             }
+            None => eprintln! {"can't save the field, since no file name"},
         }
-        _ => (),
     }
+    // an epilogue is ignored
+    io::copy(input, &mut io::sink())?;
     Ok(())
 }
 
-fn iso_8859_1_to_string(s: &[u8]) -> String {
-    s.iter().map(|&c| c as char).collect()
+#[cfg(test)]
+impl WebData {
+    /// Creates WebData of a query string, a urlencoded body and a cookie header
+    pub(crate) fn from_strings(query: &str, body: &str, cookies: &str) -> Self {
+        let mut res = WebData::new();
+        let (charset, policy) = (Charset::default(), DecodePolicy::default());
+        parse_urlencoded(query, charset, policy, &mut res.query_params);
+        parse_urlencoded(body, charset, policy, &mut res.body_params);
+        res.cookies = parse_cookie_header(cookies);
+        res
+    }
 }
 
 /// Formats specified time to HTTP timestamp format.
@@ -425,56 +809,6 @@ pub fn as_web_path(path: &mut str) -> &str {
     path
 }
 
-const BASE64: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-
-/// Performs BASE64 encoding with padding.
-///
-///
-pub fn base64_encode_with_padding(input: &[u8]) -> String {
-    let mut remain = 0_u8;
-    let mut remain_len = 0;
-    let base64 = BASE64.as_bytes();
-    let mut res = String::new();
-    res.reserve(input.len() + (input.len() + 5) / 3);
-    for b in input {
-        match remain_len {
-            0 => {
-                remain = b & 3;
-                let i = b >> 2;
-                remain_len = 2;
-                res.push(base64[i as usize] as char);
-            }
-            2 => {
-                let i = remain << 4 | (b >> 4);
-                res.push(base64[i as usize] as char);
-                remain = b & 15;
-                remain_len = 4;
-            }
-            4 => {
-                let i = remain << 2 | ((b >> 6) & 3);
-                res.push(base64[i as usize] as char);
-                remain = 0;
-                remain_len = 0;
-                res.push(base64[(b & 63) as usize] as char);
-            }
-            _ => (),
-        }
-    }
-    // padding
-    match remain_len {
-        2 => {
-            res.push(base64[(remain << 4) as usize] as char);
-            res.push_str("==")
-        }
-        4 => {
-            res.push(base64[(remain << 2) as usize] as char);
-            res.push('=')
-        }
-        _ => (),
-    }
-    res
-}
-
 /// Helps to get an attachment directory
 ///
 pub fn get_attachment_dir() -> PathBuf {
@@ -483,3 +817,153 @@ pub fn get_attachment_dir() -> PathBuf {
         _ => env::current_dir().unwrap_or_else(|_err| PathBuf::from(".")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStorage;
+
+    fn parse(
+        body: &str,
+        config: &DataConfig,
+        params: &mut Vec<(String, String)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let media = MediaType::parse("multipart/form-data; boundary=B").unwrap();
+        parse_multipart(
+            &media,
+            &mut body.as_bytes(),
+            config,
+            &mut MemoryStorage::default(),
+            params,
+            &mut Vec::new(),
+        )
+    }
+
+    fn field(name: &str, val: &str) -> String {
+        format! {"--B\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{val}\r\n"}
+    }
+
+    #[test]
+    fn field_limit() {
+        let config = DataConfig {
+            max_field_len: Some(4),
+            ..Default::default()
+        };
+        let body =
+            [field("a", "1234"), field("b", "12345"), field("c", "x")].concat() + "--B--\r\n";
+        let mut params = Vec::new();
+        assert!(parse(&body, &config, &mut params).is_ok());
+        assert_eq!(
+            params,
+            [
+                ("a".to_string(), "1234".to_string()),
+                ("c".to_string(), "x".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn protected_cookies() {
+        let keys = CookieKeys::new(b"a secret of 32 bytes for testing").unwrap();
+        let signed = keys.sign("user", "joe");
+        let names = [
+            ("user".to_string(), Protection::Signed),
+            ("theme".to_string(), Protection::Encrypted),
+        ];
+        let cookies = format! {"user={signed}; theme=dark; lang=en"};
+        let mut data = WebData::new();
+        data.cookies = parse_cookie_header(&cookies);
+        data.verify_cookies(Some(&keys), &names);
+        assert_eq!(data.cookie("user").as_deref(), Some("joe"));
+        assert_eq!(data.cookie("theme"), None);
+        assert_eq!(data.cookie_error("theme"), Some(CookieError::Malformed));
+        assert_eq!(data.cookie("lang").as_deref(), Some("en"));
+        let mut data = WebData::new();
+        data.cookies = parse_cookie_header(&cookies.replace("joe", "ann"));
+        data.verify_cookies(Some(&keys), &names);
+        assert_eq!(data.cookie("user"), None);
+        let mut data = WebData::new();
+        data.cookies = parse_cookie_header(&cookies);
+        data.verify_cookies(None, &names);
+        assert_eq!(data.cookie("user"), None);
+        assert_eq!(data.cookie_error("user"), Some(CookieError::BadSignature));
+    }
+
+    #[test]
+    fn shared_uploads() -> Result<(), Box<dyn Error>> {
+        let dir = env::temp_dir().join(crate::random_hex(8)?);
+        let file = |name: &str| {
+            format! {"--B\r\nContent-Disposition: form-data; name=\"f\"; filename=\"{name}\"\r\n\r\nsame\r\n"}
+        };
+        let body = file("a.txt") + &file("b.txt") + "--B--\r\n";
+        let media = MediaType::parse("multipart/form-data; boundary=B").unwrap();
+        let mut uploads = Vec::new();
+        parse_multipart(
+            &media,
+            &mut body.as_bytes(),
+            &DataConfig::default(),
+            &mut crate::ContentAddressedStorage::new(dir.join("blobs")),
+            &mut Vec::new(),
+            &mut uploads,
+        )?;
+        assert_eq!(uploads[0].path, uploads[1].path);
+        uploads[0].move_to(dir.join("a.txt"))?;
+        assert_eq!(std::fs::read(&uploads[1].path)?, b"same");
+        uploads[1].persist_in(&dir, CollisionPolicy::UniqueSuffix)?;
+        assert_eq!(uploads[1].path, dir.join("b.txt"));
+        assert_eq!(std::fs::read(dir.join("a.txt"))?, b"same");
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn field_digest() {
+        let digest = |val: &str, md5: &str| {
+            format! {"--B\r\nContent-Disposition: form-data; name=\"{val}\"\r\nContent-MD5: {md5}\r\n\r\n{val}\r\n"}
+        };
+        // MD5 of "a" is 0cc175b9c0f1b6a831c399e269772661
+        let body = digest("a", "DMF1ucDxtqgxw5niaXcmYQ==")
+            + &digest("b", "DMF1ucDxtqgxw5niaXcmYQ==")
+            + "--B--\r\n";
+        let mut params = Vec::new();
+        assert!(parse(&body, &DataConfig::default(), &mut params).is_ok());
+        assert_eq!(params, [("a".to_string(), "a".to_string())]);
+    }
+
+    #[test]
+    fn unclaimed_uploads() {
+        let files = MemoryStorage::default();
+        let config = DataConfig {
+            remove_unclaimed: true,
+            ..Default::default()
+        };
+        let mut data = WebData::with_storage(&config, files.clone());
+        let file = |name: &str| {
+            format! {"--B\r\nContent-Disposition: form-data; name=\"{name}\"; filename=\"{name}.txt\"\r\n\r\n{name}\r\n"}
+        };
+        let body = file("a") + &file("b") + "--B--\r\n";
+        let media = MediaType::parse("multipart/form-data; boundary=B").unwrap();
+        parse_multipart(
+            &media,
+            &mut body.as_bytes(),
+            &config,
+            data.storage.as_mut(),
+            &mut data.body_params,
+            &mut data.uploads,
+        )
+        .unwrap();
+        assert_eq!(files.locations().len(), 2);
+        data.uploads[1].keep();
+        let kept = data.uploads[1].path.clone();
+        drop(data);
+        assert_eq!(files.locations(), [kept]);
+    }
+
+    #[test]
+    fn fields_before_error() {
+        let body = field("a", "caf\u{e9}") + &field("b", "2");
+        let mut params = Vec::new();
+        assert!(parse(&body, &DataConfig::default(), &mut params).is_err());
+        assert_eq!(params, [("a".to_string(), "caf\u{e9}".to_string())]);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{FieldError, FieldErrorKind, ValidationErrors, WebData};
+
+/// Defines where a value of a field is taken from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamSource {
+    /// a query string or a body of the request
+    Param,
+    Query,
+    Body,
+    Cookie,
+    /// a segment of the path info with the given index, empty segments aren't counted
+    Path(usize),
+}
+
+/// A trait implemented by structures which can be filled from `WebData`
+///
+/// Generally it's implemented using the `web_form!` macro.
+pub trait FromWebData: Sized {
+    fn from_web_data(data: &WebData) -> Result<Self, ValidationErrors>;
+}
+
+/// A type of a single parameter value
+///
+/// It's implemented for `String`, `bool`, `char` and numbers, and can be implemented for
+/// custom types.
+pub trait ParamValue: Sized {
+    /// Parses a trimmed value, `None` means the value has an invalid format
+    fn parse_param(val: &str) -> Option<Self>;
+}
+
+macro_rules! param_value_from_str {
+    ($($ty:ty),*) => {
+        $(impl ParamValue for $ty {
+            fn parse_param(val: &str) -> Option<Self> {
+                val.parse().ok()
+            }
+        })*
+    };
+}
+
+param_value_from_str!(
+    char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64
+);
+
+impl ParamValue for String {
+    fn parse_param(val: &str) -> Option<Self> {
+        Some(val.to_string())
+    }
+}
+
+impl ParamValue for bool {
+    /// Accepts values of a checkbox as `on` and common forms as `true`, `yes` and `1`
+    fn parse_param(val: &str) -> Option<Self> {
+        match val.to_ascii_lowercase().as_str() {
+            "on" | "true" | "yes" | "1" => Some(true),
+            "off" | "false" | "no" | "0" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// A type of a struct field filled from all values of a parameter
+///
+/// A field can be a `ParamValue`, or `Option` and `Vec` of it.
+pub trait FromParams: Sized {
+    /// Converts values of a parameter, `Ok(None)` means no values were provided
+    fn from_params(name: &str, values: &[String]) -> Result<Option<Self>, Vec<FieldError>>;
+
+    /// Returns a value used when a parameter is absent and no default is specified,
+    /// `None` means the parameter is required
+    fn absent() -> Option<Self> {
+        None
+    }
+}
+
+fn parse_value<T: ParamValue>(name: &str, val: &str) -> Result<T, FieldError> {
+    T::parse_param(val.trim()).ok_or_else(|| {
+        FieldError::new(
+            name,
+            FieldErrorKind::Parse,
+            format! {"the value '{val}' has an invalid format"},
+        )
+    })
+}
+
+impl<T: ParamValue> FromParams for T {
+    fn from_params(name: &str, values: &[String]) -> Result<Option<Self>, Vec<FieldError>> {
+        match values {
+            [] => Ok(None),
+            [val] if val.trim().is_empty() => Ok(None),
+            [val] => parse_value(name, val).map(Some).map_err(|err| vec![err]),
+            _ => Err(vec![FieldError::new(
+                name,
+                FieldErrorKind::Multiple,
+                format! {"a single value is expected, but {} provided", values.len()},
+            )]),
+        }
+    }
+}
+
+impl<T: ParamValue> FromParams for Option<T> {
+    fn from_params(name: &str, values: &[String]) -> Result<Option<Self>, Vec<FieldError>> {
+        T::from_params(name, values).map(|val| val.map(Some))
+    }
+
+    fn absent() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<T: ParamValue> FromParams for Vec<T> {
+    fn from_params(name: &str, values: &[String]) -> Result<Option<Self>, Vec<FieldError>> {
+        let mut res = Vec::with_capacity(values.len());
+        let mut errors = Vec::new();
+        for val in values.iter().filter(|val| !val.trim().is_empty()) {
+            match parse_value(name, val) {
+                Ok(val) => res.push(val),
+                Err(err) => errors.push(err),
+            }
+        }
+        if !errors.is_empty() {
+            Err(errors)
+        } else if res.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(res))
+        }
+    }
+
+    fn absent() -> Option<Self> {
+        Some(Vec::new())
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __param_source {
+    (param) => {
+        $crate::ParamSource::Param
+    };
+    (query) => {
+        $crate::ParamSource::Query
+    };
+    (body) => {
+        $crate::ParamSource::Body
+    };
+    (cookie) => {
+        $crate::ParamSource::Cookie
+    };
+    (path ($idx:literal)) => {
+        $crate::ParamSource::Path($idx)
+    };
+}
+
+/// Declares a struct and implements `FromWebData` for it
+///
+/// Every field is declared with its source: `param` (query or body), `query`, `body`,
+/// `cookie` or `path(N)`, where N is an index of a path info segment. The source follows the field
+/// visibility. A parameter name is the field name.
+/// A field can have a default value used when the parameter is absent or empty.
+/// Fields of `Option` and `Vec` types aren't required, other fields without a default are required.
+/// All missing, parse and multi-value errors are collected in one `ValidationErrors`.
+///
+/// # Examples
+/// ```
+/// simweb::web_form! {
+///     #[derive(Debug)]
+///     pub struct Order {
+///         pub path(0) id: u64,
+///         pub param qty: u32 = 1,
+///         pub(crate) body note: Option<String>,
+///         query tags: Vec<String>,
+///         cookie theme: String = "light".to_string(),
+///     }
+/// }
+///
+/// let order = Order::from_web_data(&WebData::new())?;
+/// ```
+#[macro_export]
+macro_rules! web_form {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $( $field_vis:vis $src:ident $( ($idx:literal) )? $field:ident : $ty:ty $( = $default:expr )? ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $( $field_vis $field: $ty, )*
+        }
+
+        impl $crate::FromWebData for $name {
+            fn from_web_data(data: &$crate::WebData) -> Result<Self, $crate::ValidationErrors> {
+                let mut errors = $crate::ValidationErrors::default();
+                $(
+                    let name = stringify!($field);
+                    let values = data.values_from($crate::__param_source!($src $( ($idx) )?), name);
+                    let $field: Option<$ty> =
+                        match <$ty as $crate::FromParams>::from_params(name, &values) {
+                            Ok(Some(val)) => Some(val),
+                            Ok(None) => {
+                                let default: Option<$ty> = None $( .or_else(|| Some($default)) )?;
+                                match default.or_else(<$ty as $crate::FromParams>::absent) {
+                                    Some(val) => Some(val),
+                                    None => {
+                                        errors.push($crate::FieldError::new(
+                                            name,
+                                            $crate::FieldErrorKind::Missing,
+                                            "a value is required",
+                                        ));
+                                        None
+                                    }
+                                }
+                            }
+                            Err(errs) => {
+                                errors.errors.extend(errs);
+                                None
+                            }
+                        };
+                )*
+                if !errors.is_empty() {
+                    return Err(errors);
+                }
+                Ok($name {
+                    // all fields are set when there are no errors
+                    $( $field: $field.unwrap(), )*
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::web_form! {
+        #[derive(Debug)]
+        struct Order {
+            pub path(1) id: u64,
+            param qty: u32 = 1,
+            body note: Option<String>,
+            query tags: Vec<String>,
+            pub(crate) cookie theme: String = "light".to_string(),
+        }
+    }
+
+    #[test]
+    fn web_form() {
+        // SAFETY: no other test reads PATH_INFO, and Rust synchronizes its environment access
+        unsafe { std::env::set_var("PATH_INFO", "/orders//42/") }
+        let data = WebData::from_strings("tags=a&tags=b&qty=3", "note=fast", "theme=dark");
+        let order = Order::from_web_data(&data).unwrap();
+        assert_eq!(order.id, 42);
+        assert_eq!(order.qty, 3);
+        assert_eq!(order.note.as_deref(), Some("fast"));
+        assert_eq!(order.tags, ["a", "b"]);
+        assert_eq!(order.theme, "dark");
+
+        let data = WebData::from_strings("qty=x", "", "");
+        let errors = Order::from_web_data(&data).unwrap_err();
+        // `id` is parsed from the path, but the form still fails
+        let kinds: Vec<_> = errors
+            .errors
+            .iter()
+            .map(|err| (err.field.as_str(), err.kind))
+            .collect();
+        assert_eq!(kinds, [("qty", FieldErrorKind::Parse)]);
+
+        unsafe { std::env::set_var("PATH_INFO", "/orders/") }
+        let data = WebData::from_strings("qty=1&qty=2", "", "");
+        let kinds: Vec<_> = Order::from_web_data(&data)
+            .unwrap_err()
+            .errors
+            .into_iter()
+            .map(|err| (err.field, err.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("id".to_string(), FieldErrorKind::Missing),
+                ("qty".to_string(), FieldErrorKind::Multiple)
+            ]
+        );
+    }
+}
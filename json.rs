@@ -0,0 +1,417 @@
+use crate::{WebError, json_encode};
+
+const MAX_DEPTH: usize = 64;
+/// integers up to 2^53 are exact in f64
+const MAX_SAFE_INT: f64 = 9_007_199_254_740_992.0;
+
+/// A JSON value, members of an object keep their order
+///
+/// # Examples
+/// ```
+/// let val = JsonValue::parse(r#"{"sub":"joe","admin":true,"exp":1700000000}"#)?;
+/// assert_eq!(val.get("sub").and_then(JsonValue::as_str), Some("joe"));
+/// assert_eq!(val.get("exp").and_then(JsonValue::as_f64), Some(1700000000.0));
+/// assert_eq!(val.to_json(), r#"{"sub":"joe","admin":true,"exp":1700000000}"#);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Parses a JSON text, an error tells a byte position of the problem
+    pub fn parse(text: &str) -> Result<JsonValue, WebError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let res = parser.value(0)?;
+        parser.skip_ws();
+        if parser.pos < parser.text.len() {
+            return Err(parser.error("unexpected data after the value"));
+        }
+        Ok(res)
+    }
+
+    /// Returns a member of an object
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, val)| val),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Serializes the value without white spaces
+    pub fn to_json(&self) -> String {
+        let mut res = String::new();
+        self.write_json(&mut res);
+        res
+    }
+
+    fn write_json(&self, res: &mut String) {
+        match self {
+            JsonValue::Null => res.push_str("null"),
+            JsonValue::Bool(val) => res.push_str(if *val { "true" } else { "false" }),
+            JsonValue::Number(val) if !val.is_finite() => res.push_str("null"),
+            JsonValue::Number(val) if val.fract() == 0.0 && val.abs() <= MAX_SAFE_INT => {
+                res.push_str(&format! {"{}", *val as i64})
+            }
+            JsonValue::Number(val) => res.push_str(&format! {"{val}"}),
+            JsonValue::String(val) => {
+                res.push('"');
+                res.push_str(&json_encode(val));
+                res.push('"')
+            }
+            JsonValue::Array(items) => {
+                res.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        res.push(',')
+                    }
+                    item.write_json(res)
+                }
+                res.push(']')
+            }
+            JsonValue::Object(members) => {
+                res.push('{');
+                for (i, (name, val)) in members.iter().enumerate() {
+                    if i > 0 {
+                        res.push(',')
+                    }
+                    res.push('"');
+                    res.push_str(&json_encode(name));
+                    res.push_str("\":");
+                    val.write_json(res)
+                }
+                res.push('}')
+            }
+        }
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(val: &str) -> Self {
+        JsonValue::String(val.to_string())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(val: String) -> Self {
+        JsonValue::String(val)
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(val: bool) -> Self {
+        JsonValue::Bool(val)
+    }
+}
+
+impl From<f64> for JsonValue {
+    fn from(val: f64) -> Self {
+        JsonValue::Number(val)
+    }
+}
+
+impl From<i64> for JsonValue {
+    fn from(val: i64) -> Self {
+        JsonValue::Number(val as f64)
+    }
+}
+
+impl From<u64> for JsonValue {
+    fn from(val: u64) -> Self {
+        JsonValue::Number(val as f64)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> WebError {
+        WebError {
+            reason: format! {"invalid JSON at {}: {reason}", self.pos},
+            cause: None,
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.text.get(self.pos) {
+            self.pos += 1
+        }
+    }
+
+    fn expect(&mut self, literal: &str, val: JsonValue) -> Result<JsonValue, WebError> {
+        if self.text[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(val)
+        } else {
+            Err(self.error("unknown literal"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<JsonValue, WebError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deep nesting"));
+        }
+        self.skip_ws();
+        match self.text.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_ws();
+                if self.text.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                loop {
+                    self.skip_ws();
+                    if self.text.get(self.pos) != Some(&b'"') {
+                        return Err(self.error("a member name expected"));
+                    }
+                    let name = self.string()?;
+                    self.skip_ws();
+                    if self.text.get(self.pos) != Some(&b':') {
+                        return Err(self.error("':' expected"));
+                    }
+                    self.pos += 1;
+                    members.push((name, self.value(depth + 1)?));
+                    self.skip_ws();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Object(members));
+                        }
+                        _ => return Err(self.error("',' or '}' expected")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_ws();
+                if self.text.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_ws();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Array(items));
+                        }
+                        _ => return Err(self.error("',' or ']' expected")),
+                    }
+                }
+            }
+            Some(b'"') => Ok(JsonValue::String(self.string()?)),
+            Some(b't') => self.expect("true", JsonValue::Bool(true)),
+            Some(b'f') => self.expect("false", JsonValue::Bool(false)),
+            Some(b'n') => self.expect("null", JsonValue::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<JsonValue, WebError> {
+        let start = self.pos;
+        if self.text[self.pos] == b'-' {
+            self.pos += 1
+        }
+        let digits = |parser: &mut Self| {
+            let from = parser.pos;
+            while parser.text.get(parser.pos).is_some_and(u8::is_ascii_digit) {
+                parser.pos += 1
+            }
+            parser.pos - from
+        };
+        let int_start = self.pos;
+        match digits(self) {
+            0 => return Err(self.error("a digit expected")),
+            n if n > 1 && self.text[int_start] == b'0' => {
+                return Err(self.error("a leading zero"));
+            }
+            _ => (),
+        }
+        if self.text.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if digits(self) == 0 {
+                return Err(self.error("a fraction digit expected"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.text.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.text.get(self.pos) {
+                self.pos += 1
+            }
+            if digits(self) == 0 {
+                return Err(self.error("an exponent digit expected"));
+            }
+        }
+        // the slice has only ASCII characters
+        let num = std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default();
+        num.parse()
+            .map(JsonValue::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, WebError> {
+        // `from_str_radix` alone accepts a sign
+        let hex = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(hex)
+    }
+
+    /// parses a string starting at the opening quote
+    fn string(&mut self) -> Result<String, WebError> {
+        self.pos += 1;
+        let mut res = Vec::new();
+        loop {
+            let Some(&b) = self.text.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&esc) = self.text.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match esc {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("invalid surrogate pair"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00)
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error("invalid code point"))?
+                        }
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    res.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+                }
+                0..0x20 => return Err(self.error("a control character in a string")),
+                _ => res.push(b),
+            }
+        }
+        // the input is a str, and escapes produce valid UTF-8
+        String::from_utf8(res).map_err(|_| self.error("invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        let text = "q\"b\\s/n\nt\tc\u{1}\u{1f}é😀";
+        let json = JsonValue::from(text).to_json();
+        assert_eq!(json, "\"q\\\"b\\\\s/n\\nt\\tc\\u0001\\u001fé😀\"");
+        assert_eq!(JsonValue::parse(&json).ok(), Some(JsonValue::from(text)));
+        assert_eq!(
+            JsonValue::parse(r#""é\/\b\f\r😀""#).ok(),
+            Some(JsonValue::from("é/\u{8}\u{c}\r😀"))
+        );
+        for invalid in [
+            r#""\u+123""#,
+            r#""\u-123""#,
+            r#""\u12""#,
+            r#""\ud83d""#,
+            r#""\ud83dx""#,
+            r#""\ud83dA""#,
+            r#""\ude00""#,
+            r#""\x""#,
+            "\"a\u{1}\"",
+        ] {
+            assert!(JsonValue::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(JsonValue::parse(&nested(MAX_DEPTH + 1)).is_ok());
+        assert!(JsonValue::parse(&nested(MAX_DEPTH + 2)).is_err());
+        assert!(JsonValue::parse(&"{\"a\":".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"a":[1,-2.5,1e3,null,true,false],"b":{},"c":"x"}"#;
+        let val = JsonValue::parse(text).unwrap();
+        assert_eq!(
+            val.to_json(),
+            r#"{"a":[1,-2.5,1000,null,true,false],"b":{},"c":"x"}"#
+        );
+        assert_eq!(JsonValue::parse(&val.to_json()).ok(), Some(val));
+        for invalid in ["01", "1.", "-", "[1,]", "{\"a\" 1}", "tru", "1 2", ""] {
+            assert!(JsonValue::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
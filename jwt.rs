@@ -0,0 +1,348 @@
+use crate::{
+    Base64, JsonValue,
+    hash::{constant_time_eq, hmac_sha256},
+};
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// A reason of a rejected JSON Web Token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JwtError {
+    /// the token isn't three base64url parts of JSON objects, or a claim has a wrong type
+    Malformed,
+    /// the algorithm isn't HS256, or the header has critical extensions
+    UnsupportedAlgorithm,
+    BadSignature,
+    /// `exp` has passed
+    Expired,
+    /// `nbf` hasn't come yet
+    NotYetValid,
+    /// `iat` is in the future
+    IssuedInFuture,
+    /// `aud` doesn't list the expected audience
+    WrongAudience,
+    /// `iss` isn't the expected issuer
+    WrongIssuer,
+    /// a required claim is absent
+    MissingClaim(&'static str),
+}
+
+impl Error for JwtError {}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JwtError::Malformed => write!(f, "malformed token"),
+            JwtError::UnsupportedAlgorithm => write!(f, "unsupported token algorithm"),
+            JwtError::BadSignature => write!(f, "token signature mismatch"),
+            JwtError::Expired => write!(f, "token expired"),
+            JwtError::NotYetValid => write!(f, "token not valid yet"),
+            JwtError::IssuedInFuture => write!(f, "token issued in the future"),
+            JwtError::WrongAudience => write!(f, "token audience mismatch"),
+            JwtError::WrongIssuer => write!(f, "token issuer mismatch"),
+            JwtError::MissingClaim(claim) => write!(f, "token has no claim {claim}"),
+        }
+    }
+}
+
+/// Checks applied to claims of a token
+#[derive(Debug, Clone)]
+pub struct JwtValidation {
+    /// a tolerated clock difference between the issuer and the server
+    pub leeway: Duration,
+    /// `aud` has to list it, a token with `aud` is rejected when it's `None`
+    pub audience: Option<String>,
+    /// `iss` has to be equal to it
+    pub issuer: Option<String>,
+    /// a token without `exp` is rejected
+    pub require_exp: bool,
+}
+
+impl Default for JwtValidation {
+    /// 60 seconds leeway and required `exp`
+    fn default() -> Self {
+        JwtValidation {
+            leeway: Duration::from_secs(60),
+            audience: None,
+            issuer: None,
+            require_exp: true,
+        }
+    }
+}
+
+/// Creates a JSON Web Token signed by HS256
+///
+/// `iat` is set to the current time, and `exp` is set after `ttl` when it's given,
+/// unless the claims already have them.
+///
+/// # Examples
+/// ```
+/// let token = jwt_encode(&[("sub", "joe".into()), ("iss", "myapp".into())],
+///     Some(Duration::from_secs(3600)), b"a secret of 32 bytes or more....");
+/// ```
+pub fn jwt_encode(claims: &[(&str, JsonValue)], ttl: Option<Duration>, secret: &[u8]) -> String {
+    let now = now_secs();
+    let mut members = Vec::with_capacity(claims.len() + 2);
+    if !claims.iter().any(|(name, _)| *name == "iat") {
+        members.push(("iat".to_string(), JsonValue::from(now)))
+    }
+    if let Some(ttl) = ttl
+        && !claims.iter().any(|(name, _)| *name == "exp")
+    {
+        members.push(("exp".to_string(), JsonValue::from(now + ttl.as_secs())))
+    }
+    members.extend(
+        claims
+            .iter()
+            .map(|(name, val)| (name.to_string(), val.clone())),
+    );
+    let header = Base64::URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+    let payload = Base64::URL_SAFE_NO_PAD.encode(JsonValue::Object(members).to_json().as_bytes());
+    let signing_input = format! {"{header}.{payload}"};
+    let sign = Base64::URL_SAFE_NO_PAD.encode(&hmac_sha256(secret, signing_input.as_bytes()));
+    format! {"{signing_input}.{sign}"}
+}
+
+/// Verifies a JSON Web Token signed by HS256 and returns its claims
+///
+/// The signature is checked before any claim. `exp`, `nbf` and `iat` are numeric dates
+/// compared with the current time allowing `leeway`.
+///
+/// # Examples
+/// ```
+/// let validation = JwtValidation { issuer: Some("myapp".to_string()), ..Default::default() };
+/// let claims = jwt_decode(&bearer_token().ok_or("no token")?, SECRET, &validation)?;
+/// let user = claims.get("sub").and_then(JsonValue::as_str);
+/// ```
+pub fn jwt_decode(
+    token: &str,
+    secret: &[u8],
+    validation: &JwtValidation,
+) -> Result<JsonValue, JwtError> {
+    let token = token.trim();
+    let mut parts = token.split('.');
+    let (Some(header_part), Some(payload), Some(sign), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(JwtError::Malformed);
+    };
+    let header = decode_object(header_part)?;
+    if header.get("alg").and_then(JsonValue::as_str) != Some("HS256")
+        || header.get("crit").is_some()
+    {
+        return Err(JwtError::UnsupportedAlgorithm);
+    }
+    let signing_input = &token[..header_part.len() + 1 + payload.len()];
+    // encoded forms are compared, so unused bits of the last character can't be altered
+    let expected = Base64::URL_SAFE_NO_PAD.encode(&hmac_sha256(secret, signing_input.as_bytes()));
+    if !constant_time_eq(expected.as_bytes(), sign.as_bytes()) {
+        return Err(JwtError::BadSignature);
+    }
+    let claims = decode_object(payload)?;
+    check_claims(&claims, validation)?;
+    Ok(claims)
+}
+
+fn decode_object(part: &str) -> Result<JsonValue, JwtError> {
+    let json = Base64::URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| JwtError::Malformed)?;
+    let json = String::from_utf8(json).map_err(|_| JwtError::Malformed)?;
+    match JsonValue::parse(&json) {
+        Ok(obj @ JsonValue::Object(_)) => Ok(obj),
+        _ => Err(JwtError::Malformed),
+    }
+}
+
+fn check_claims(claims: &JsonValue, validation: &JwtValidation) -> Result<(), JwtError> {
+    let now = now_secs() as f64;
+    let leeway = validation.leeway.as_secs_f64();
+    let date = |name| match claims.get(name) {
+        Some(val) => val.as_f64().map(Some).ok_or(JwtError::Malformed),
+        None => Ok(None),
+    };
+    match date("exp")? {
+        Some(exp) if now - leeway >= exp => return Err(JwtError::Expired),
+        None if validation.require_exp => return Err(JwtError::MissingClaim("exp")),
+        _ => (),
+    }
+    if let Some(nbf) = date("nbf")?
+        && now + leeway < nbf
+    {
+        return Err(JwtError::NotYetValid);
+    }
+    if let Some(iat) = date("iat")?
+        && now + leeway < iat
+    {
+        return Err(JwtError::IssuedInFuture);
+    }
+    match (claims.get("aud"), &validation.audience) {
+        (None, None) => (),
+        (None, Some(_)) => return Err(JwtError::MissingClaim("aud")),
+        (Some(_), None) => return Err(JwtError::WrongAudience),
+        (Some(JsonValue::String(aud)), Some(expected)) if aud == expected => (),
+        (Some(JsonValue::Array(auds)), Some(expected))
+            if auds.iter().any(|aud| aud.as_str() == Some(expected)) => {}
+        (Some(JsonValue::String(_) | JsonValue::Array(_)), Some(_)) => {
+            return Err(JwtError::WrongAudience);
+        }
+        (Some(_), Some(_)) => return Err(JwtError::Malformed),
+    }
+    if let Some(expected) = &validation.issuer {
+        match claims.get("iss") {
+            Some(JsonValue::String(iss)) if iss == expected => (),
+            Some(JsonValue::String(_)) => return Err(JwtError::WrongIssuer),
+            Some(_) => return Err(JwtError::Malformed),
+            None => return Err(JwtError::MissingClaim("iss")),
+        }
+    }
+    Ok(())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"a secret of 32 bytes for testing";
+
+    /// signs any header and payload
+    fn token(header: &str, payload: &str) -> String {
+        let input = format! {"{}.{}", Base64::URL_SAFE_NO_PAD.encode(header.as_bytes()),
+        Base64::URL_SAFE_NO_PAD.encode(payload.as_bytes())};
+        let sign = Base64::URL_SAFE_NO_PAD.encode(&hmac_sha256(SECRET, input.as_bytes()));
+        format! {"{input}.{sign}"}
+    }
+
+    fn decode(payload: &str) -> Result<JsonValue, JwtError> {
+        jwt_decode(
+            &token(r#"{"alg":"HS256"}"#, payload),
+            SECRET,
+            &JwtValidation::default(),
+        )
+    }
+
+    #[test]
+    fn signature() {
+        let jwt = jwt_encode(
+            &[("sub", "joe".into())],
+            Some(Duration::from_secs(60)),
+            SECRET,
+        );
+        let claims = jwt_decode(&jwt, SECRET, &JwtValidation::default()).unwrap();
+        assert_eq!(claims.get("sub").and_then(JsonValue::as_str), Some("joe"));
+        assert_eq!(
+            jwt_decode(
+                &jwt,
+                b"another secret of 32 bytes......",
+                &JwtValidation::default()
+            ),
+            Err(JwtError::BadSignature)
+        );
+        let (input, sign) = jwt.rsplit_once('.').unwrap();
+        let (header, _) = input.split_once('.').unwrap();
+        let payload = Base64::URL_SAFE_NO_PAD.encode(br#"{"sub":"admin","exp":99999999999}"#);
+        let forged = format! {"{header}.{payload}.{sign}"};
+        assert_eq!(
+            jwt_decode(&forged, SECRET, &JwtValidation::default()),
+            Err(JwtError::BadSignature)
+        );
+        let flipped = if sign.ends_with('A') { "B" } else { "A" };
+        let tampered = format! {"{input}.{}{flipped}", &sign[..sign.len() - 1]};
+        assert_eq!(
+            jwt_decode(&tampered, SECRET, &JwtValidation::default()),
+            Err(JwtError::BadSignature)
+        );
+        assert_eq!(
+            jwt_decode("a.b", SECRET, &JwtValidation::default()),
+            Err(JwtError::Malformed)
+        );
+    }
+
+    #[test]
+    fn algorithm() {
+        let exp = now_secs() + 60;
+        for header in [
+            r#"{"alg":"none"}"#,
+            r#"{"alg":"HS512"}"#,
+            r#"{"alg":"hs256"}"#,
+            r#"{"typ":"JWT"}"#,
+            r#"{"alg":"HS256","crit":["exp"]}"#,
+        ] {
+            let jwt = token(header, &format! {r#"{{"exp":{exp}}}"#});
+            assert_eq!(
+                jwt_decode(&jwt, SECRET, &JwtValidation::default()),
+                Err(JwtError::UnsupportedAlgorithm),
+                "{header}"
+            );
+        }
+    }
+
+    #[test]
+    fn dates() {
+        let now = now_secs();
+        assert!(decode(&format! {r#"{{"exp":{}}}"#, now - 30}).is_ok());
+        assert_eq!(
+            decode(&format! {r#"{{"exp":{}}}"#, now - 120}),
+            Err(JwtError::Expired)
+        );
+        assert_eq!(decode("{}"), Err(JwtError::MissingClaim("exp")));
+        assert_eq!(decode(r#"{"exp":"soon"}"#), Err(JwtError::Malformed));
+        let exp = now + 600;
+        assert!(decode(&format! {r#"{{"exp":{exp},"nbf":{}}}"#, now + 30}).is_ok());
+        assert_eq!(
+            decode(&format! {r#"{{"exp":{exp},"nbf":{}}}"#, now + 120}),
+            Err(JwtError::NotYetValid)
+        );
+        assert_eq!(
+            decode(&format! {r#"{{"exp":{exp},"iat":{}}}"#, now + 120}),
+            Err(JwtError::IssuedInFuture)
+        );
+        let strict = JwtValidation {
+            leeway: Duration::ZERO,
+            ..Default::default()
+        };
+        let jwt = token(r#"{"alg":"HS256"}"#, &format! {r#"{{"exp":{}}}"#, now - 1});
+        assert_eq!(jwt_decode(&jwt, SECRET, &strict), Err(JwtError::Expired));
+    }
+
+    #[test]
+    fn audience_and_issuer() {
+        let exp = now_secs() + 60;
+        let validation = JwtValidation {
+            audience: Some("api".to_string()),
+            issuer: Some("app".to_string()),
+            ..Default::default()
+        };
+        let check = |claims: &str| {
+            let jwt = token(
+                r#"{"alg":"HS256"}"#,
+                &format! {r#"{{"exp":{exp},{claims}}}"#},
+            );
+            jwt_decode(&jwt, SECRET, &validation).map(|_| ())
+        };
+        assert_eq!(check(r#""aud":["web","api"],"iss":"app""#), Ok(()));
+        assert_eq!(
+            check(r#""aud":"web","iss":"app""#),
+            Err(JwtError::WrongAudience)
+        );
+        assert_eq!(
+            check(r#""aud":"api","iss":"other""#),
+            Err(JwtError::WrongIssuer)
+        );
+        assert_eq!(check(r#""iss":"app""#), Err(JwtError::MissingClaim("aud")));
+        assert_eq!(
+            decode(&format! {r#"{{"exp":{exp},"aud":"api"}}"#}),
+            Err(JwtError::WrongAudience)
+        );
+    }
+}
@@ -20,20 +20,81 @@
 //! }
 //!
 //! ```
+mod auth;
+mod base64;
+mod chacha;
+mod charset;
+mod checksum;
+mod cookie;
+mod csrf;
 mod data;
+mod form;
+mod hash;
+mod json;
+mod jwt;
+mod media;
 mod mpart;
+mod nested;
+mod progress;
+mod random;
+mod session;
 mod simweb;
+mod storage;
 mod template;
+mod upload;
 mod util;
+mod validate;
 
+pub use auth::{
+    Htpasswd, PBKDF2_ITERATIONS, basic_credentials, bearer_token, hash_password, needs_rehash,
+    parse_basic_credentials, parse_bearer_token, pbkdf2, pbkdf2_sha256, verify_hash,
+};
+pub use base64::{
+    Base64, Base64Error, Base64ErrorKind, Base64Reader, Base64Writer, base64_decode,
+    base64_encode_with_padding,
+};
+pub use charset::{Charset, DecodePolicy};
+pub use checksum::{ChecksumKind, ChecksumReader, Checksums};
+pub use cookie::{
+    Cookie, CookieError, CookieKeys, Protection, SameSite, delete_cookie_header,
+    parse_cookie_header,
+};
+pub use csrf::{
+    CSRF_COOKIE, CSRF_FIELD, csrf_field, double_submit_token, new_csrf_token, session_csrf_token,
+};
 pub use data::{
-    HTTP_DAYS_OF_WEEK, HTTP_MONTH, WebData, adjust_separator, as_web_path,
-    base64_encode_with_padding, http_format_time, parse_http_timestamp, sanitize_web_path,
+    DataConfig, HTTP_DAYS_OF_WEEK, HTTP_MONTH, MAX_FIELD_LEN, WebData, adjust_separator,
+    as_web_path, http_format_time, parse_http_timestamp, sanitize_web_path,
+};
+pub use form::{FromParams, FromWebData, ParamSource, ParamValue};
+pub use hash::{
+    Crc32, Digest, Hmac, Md5, Sha1, Sha256, Sha512, constant_time_eq, hmac_sha1, hmac_sha256,
+    hmac_sha512,
 };
-pub use mpart::MPart;
+pub use json::JsonValue;
+pub use jwt::{JwtError, JwtValidation, jwt_decode, jwt_encode};
+pub use media::{MediaType, negotiate, parse_accept};
+pub use mpart::{MPart, MultipartReader, Part, PartHeaders, PartReader, Storage, header_params};
+pub use nested::ParamTree;
+pub use progress::{
+    ProgressFile, ProgressPage, ProgressReader, ProgressSink, UPLOAD_ID_PARAM, UploadProgress,
+    clean_progress, read_progress,
+};
+pub use random::{fill_random, random_bytes, random_hex, random_token, uuid_v4, uuid_v7};
+pub use session::{Session, SessionConfig, collect_garbage};
 pub use simweb::WebPage;
+pub use storage::{
+    CallbackStorage, ContentAddressedStorage, DirStorage, MemoryStorage, UploadEvent, UploadSink,
+    UploadStorage,
+};
 pub use template::{Selectable, interpolate};
+pub use upload::{
+    CollisionPolicy, UploadedFile, create_upload_file, sanitize_filename, sniff_content_type,
+};
 pub use util::list_files;
+pub use validate::{
+    FieldError, FieldErrorKind, Rule, ValidationErrors, Validator, is_email, is_url, pattern_match,
+};
 
 use std::{borrow::Cow, env, error::Error, fmt, time::SystemTime};
 
@@ -87,6 +148,8 @@ pub fn get_version() -> &'static str {
 /// * value - a value of the cookie
 /// * expiration - an optional expiration date as `SystemTime`, it will be a session cookie, when `None`
 ///
+/// Use `Cookie` for other attributes as `Path`, `Secure` or `SameSite`.
+///
 /// # Examples
 /// ```
 /// let (set_op, val) = new_cookie_header("age", "23", None);
@@ -171,7 +234,7 @@ pub fn json_encode(orig: &str) -> Cow<'_, str> {
                 '\r' => res.push_str("\\r"),
                 '\t' => res.push_str("\\t"),
                 '\\' => res.push_str("\\\\"),
-                '\u{0000}'..'\u{1f}' => res.push_str(&format!("\\u00{:02x}", c as u8)),
+                '\u{0000}'..='\u{1f}' => res.push_str(&format!("\\u00{:02x}", c as u8)),
                 _ => res.push(c),
             }
         }
@@ -207,7 +270,7 @@ fn escaped_len(s: &str) -> (usize, usize) {
 fn escape_char(c: char) -> usize {
     match c {
         '"' | '\n' | '\r' | '\t' | '\\' => 1,
-        '\u{0000}'..'\u{1f}' => 5,
+        '\u{0000}'..='\u{1f}' => 5,
         _ => 0,
     }
 }
@@ -222,25 +285,31 @@ pub fn path_info() -> String {
 
 /// Decodes URL component.
 ///
-/// If a decoding impossible, the it returns `None`.
+/// If a decoding impossible, the it returns `None`. A not encoded non ASCII character is kept as it is.
 ///
 /// A new string is always created regardless if an actual decoding happened.
 pub fn url_comp_decode(comp: &str) -> Option<String> {
-    let mut res = Vec::with_capacity(256);
+    String::from_utf8(url_comp_decode_bytes(comp)?).ok()
+}
 
-    let mut chars = comp.chars();
-    while let Some(c) = chars.next() {
-        match c {
-            '%' => {
-                let d1 = chars.next()?.to_digit(16)?;
-                let d2 = chars.next()?.to_digit(16)?;
+/// Decodes URL component to bytes, so they can be decoded by a charset other than UTF-8.
+///
+/// A not encoded non ASCII character is kept as its UTF-8 bytes. `None` is returned for an invalid escape.
+pub fn url_comp_decode_bytes(comp: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(comp.len());
+    let mut bytes = comp.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let d1 = (bytes.next()? as char).to_digit(16)?;
+                let d2 = (bytes.next()? as char).to_digit(16)?;
                 res.push(((d1 << 4) + d2) as u8)
             }
-            '+' => res.push(b' '),
-            _ => res.push(if c.is_ascii() { c as u8 } else { return None }),
+            b'+' => res.push(b' '),
+            b => res.push(b),
         }
     }
-    String::from_utf8(res).ok()
+    Some(res)
 }
 
 /// It's encoding as URL component encode
@@ -294,3 +363,20 @@ pub fn to_hex(line: &[u8]) -> String {
     }
     s
 }
+
+/// Converts a hex string to bytes
+///
+/// Returns `None` when the string has an odd length or a non hex digit.
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks_exact(2)
+        .map(|pair| {
+            let d1 = (pair[0] as char).to_digit(16)?;
+            let d2 = (pair[1] as char).to_digit(16)?;
+            Some(((d1 << 4) + d2) as u8)
+        })
+        .collect()
+}
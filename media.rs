@@ -0,0 +1,262 @@
+use crate::WebError;
+use std::{fmt, str::FromStr};
+
+/// A media type of `Content-Type` or `Accept` as defined by RFC 9110
+///
+/// The type, the subtype and parameter names are in lowercase, parameter values are unquoted
+/// and keep their case.
+///
+/// # Examples
+/// ```
+/// let media = MediaType::parse(r#"Multipart/Form-Data; Boundary="----abc"; charset=UTF-8"#)?;
+/// assert_eq!(media.essence(), "multipart/form-data");
+/// assert_eq!(media.param("boundary"), Some("----abc"));
+/// assert_eq!(media.to_string(), "multipart/form-data; boundary=----abc; charset=UTF-8");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaType {
+    pub main: String,
+    pub subtype: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    /// Parses `type/subtype` followed by parameters, which values can be quoted strings
+    pub fn parse(val: &str) -> Result<Self, WebError> {
+        let (essence, mut rest) = val.split_once(';').unwrap_or((val, ""));
+        let Some((main, subtype)) = essence.trim().split_once('/') else {
+            return Err(media_error("no subtype", val));
+        };
+        if !is_token(main) || !is_token(subtype) {
+            return Err(media_error("invalid type", val));
+        }
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches([' ', '\t', ';']);
+            if rest.is_empty() {
+                break;
+            }
+            let (name, tail) = rest.split_once('=').unwrap_or((rest, ""));
+            let name = name.trim_end();
+            if !is_token(name) {
+                return Err(media_error("invalid parameter name", val));
+            }
+            let (param, tail) = match tail.strip_prefix('"') {
+                Some(quoted) => {
+                    let (param, tail) =
+                        unquote(quoted).ok_or_else(|| media_error("unterminated quote", val))?;
+                    if !tail.trim_start().is_empty() && !tail.trim_start().starts_with(';') {
+                        return Err(media_error("data after a quoted string", val));
+                    }
+                    (param, tail)
+                }
+                None => {
+                    let (param, tail) = tail.split_once(';').unwrap_or((tail, ""));
+                    (param.trim_end().to_string(), tail)
+                }
+            };
+            params.push((name.to_ascii_lowercase(), param));
+            rest = tail
+        }
+        Ok(MediaType {
+            main: main.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            params,
+        })
+    }
+
+    /// Returns `type/subtype` without parameters
+    pub fn essence(&self) -> String {
+        format! {"{}/{}", self.main, self.subtype}
+    }
+
+    /// Checks `type/subtype` ignoring the case and parameters
+    pub fn is(&self, essence: &str) -> bool {
+        essence.split_once('/').is_some_and(|(main, subtype)| {
+            self.main.eq_ignore_ascii_case(main.trim())
+                && self.subtype.eq_ignore_ascii_case(subtype.trim())
+        })
+    }
+
+    /// Returns a value of the parameter, the name is case insensitive
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, val)| val.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    pub fn boundary(&self) -> Option<&str> {
+        self.param("boundary")
+    }
+
+    /// Checks if the media type is in the range, like `text/html` is in `text/*` and `*/*`
+    ///
+    /// Every parameter of the range has to be present in the media type,
+    /// values of `charset` are compared ignoring the case.
+    pub fn in_range(&self, range: &MediaType) -> bool {
+        (range.main == "*" || range.main == self.main)
+            && (range.subtype == "*" || range.subtype == self.subtype)
+            && range.params.iter().all(|(name, val)| {
+                self.param(name).is_some_and(|own| {
+                    own == val || (name == "charset" && own.eq_ignore_ascii_case(val))
+                })
+            })
+    }
+
+    /// Returns a rank of a range, a more specific range has a higher rank
+    fn specificity(&self) -> usize {
+        match (self.main.as_str(), self.subtype.as_str()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ => 2 + self.params.len(),
+        }
+    }
+}
+
+impl FromStr for MediaType {
+    type Err = WebError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        MediaType::parse(val)
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.main, self.subtype)?;
+        for (name, val) in &self.params {
+            if !val.is_empty() && is_token(val) {
+                write!(f, "; {name}={val}")?
+            } else {
+                let escaped = val.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "; {name}=\"{escaped}\"")?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a value of `Accept` to media ranges with their weights
+///
+/// Ranges are ordered by the weight, ranges of an equal weight keep their order.
+/// A range which can't be parsed is skipped. `q` isn't included in parameters of a range.
+///
+/// # Examples
+/// ```
+/// let ranges = parse_accept("text/*;q=0.5, application/json");
+/// assert_eq!(ranges[0].0.essence(), "application/json");
+/// assert_eq!(ranges[1].1, 0.5);
+/// ```
+pub fn parse_accept(val: &str) -> Vec<(MediaType, f32)> {
+    let mut res: Vec<_> = split_list(val)
+        .into_iter()
+        .filter_map(|range| MediaType::parse(range).ok())
+        .map(|mut range| {
+            let mut weight = 1.0;
+            if let Some(pos) = range.params.iter().position(|(name, _)| name == "q") {
+                weight = range.params[pos]
+                    .1
+                    .parse::<f32>()
+                    .unwrap_or(0.0)
+                    .clamp(0.0, 1.0);
+                // parameters after the weight are extensions of Accept
+                range.params.truncate(pos)
+            }
+            (range, weight)
+        })
+        .collect();
+    res.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    res
+}
+
+/// Chooses the best of available media types for a value of `Accept`
+///
+/// A weight of every type is taken from the most specific range including it,
+/// a type with the weight 0 is not acceptable. The first of types with an equal weight wins.
+/// `None` is returned when nothing is acceptable, an empty `Accept` accepts the first type.
+///
+/// # Examples
+/// ```
+/// let accept = "text/html;q=0.9, application/json, */*;q=0.1";
+/// assert_eq!(negotiate(accept, &["text/html", "application/json"]), Some("application/json"));
+/// assert_eq!(negotiate("image/*", &["text/html"]), None);
+/// ```
+pub fn negotiate<'a>(accept: &str, available: &[&'a str]) -> Option<&'a str> {
+    if accept.trim().is_empty() {
+        return available.first().copied();
+    }
+    let ranges = parse_accept(accept);
+    let mut best: Option<(&str, f32)> = None;
+    for &candidate in available {
+        let Ok(media) = MediaType::parse(candidate) else {
+            continue;
+        };
+        let weight = ranges
+            .iter()
+            .filter(|(range, _)| media.in_range(range))
+            .max_by_key(|(range, _)| range.specificity())
+            .map(|(_, weight)| *weight)
+            .unwrap_or(0.0);
+        if weight > 0.0 && best.is_none_or(|(_, best)| weight > best) {
+            best = Some((candidate, weight))
+        }
+    }
+    best.map(|(candidate, _)| candidate)
+}
+
+/// Splits a comma separated list of a header, commas in quoted strings are kept
+fn split_list(val: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (pos, c) in val.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                res.push(val[start..pos].trim());
+                start = pos + 1
+            }
+            _ => (),
+        }
+    }
+    res.push(val[start..].trim());
+    res.retain(|item| !item.is_empty());
+    res
+}
+
+/// Reads a quoted string after the opening quote, `None` is returned without the closing quote
+fn unquote(quoted: &str) -> Option<(String, &str)> {
+    let mut res = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '"' => return Some((res, &quoted[pos + 1..])),
+            '\\' => res.push(chars.next()?.1),
+            c => res.push(c),
+        }
+    }
+    None
+}
+
+/// Checks a token of RFC 9110
+fn is_token(val: &str) -> bool {
+    !val.is_empty()
+        && val
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn media_error(reason: &str, val: &str) -> WebError {
+    WebError {
+        reason: format! {"invalid media type {val}: {reason}"},
+        cause: None,
+    }
+}
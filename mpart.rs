@@ -1,4 +1,8 @@
-use crate::data;
+use crate::{
+    Base64, Base64Reader, Charset, ChecksumKind, ChecksumReader, Checksums, DecodePolicy,
+    DirStorage, UploadStorage, data,
+    upload::{CollisionPolicy, sanitize_filename},
+};
 /// Represents the implementation of `multipart/form-data` formatted data.
 ///
 /// This will parse the source stream into an iterator over fields
@@ -8,26 +12,31 @@ use crate::data;
 /// source: https://andreubotella.github.io/multipart-form-data/
 /// and https://www.rfc-editor.org/rfc/rfc7578
 use std::{
-    fs::OpenOptions,
-    io::{Read, Write},
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
     path::PathBuf,
 };
 static ANTICIPATED_PART_SIZE: usize = 4096;
 pub const CHUNK_THRESHOLD: usize = 1024 * 1024 * 4;
+const BUF_LEN: usize = 4096;
+/// a limit of a part header block
+const MAX_HEADERS_LEN: usize = 16 * 1024;
 
+/// An iterator of parts, an error of reading or storing a part ends it and is kept for `error`
 pub struct MPart<'a> {
-    reader: &'a mut dyn Read,
-    boundary: Vec<u8>,
-    buffer: [u8; 4096],
-    bytes_read: usize,
-    slice_start: usize,
-    slice_end: usize,
-    first: bool,
-    last: bool,
+    multipart: MultipartReader<&'a mut dyn Read>,
+    checksums: Vec<ChecksumKind>,
+    /// a storage of big files
+    storage: Box<dyn UploadStorage>,
+    /// a limit of a part without a file name
+    max_field_len: usize,
+    /// an error ended the parts
+    error: Option<io::Error>,
 }
 
 pub enum Storage {
     Mem(Vec<u8>),
+    /// a location of a big file committed by the storage of `MPart`, the caller removes it
     Disk(PathBuf),
     None,
 }
@@ -36,286 +45,908 @@ pub enum Storage {
 ///
 /// - content-type when available
 /// - name of the part
-/// - the part file name (when specified)
+/// - the part file name (when specified), it's sanitized by `sanitize_filename`
+/// - the part file name as sent by the client
 /// - the part content
+/// - digests of the content
 pub struct Part {
     pub content_type: Option<String>,
     pub content_name: String,
     pub total_read_ammount: usize,
     pub content_filename: Option<String>,
+    pub original_filename: Option<String>,
     pub content: Storage, //Vec<u8>,
+    pub checksums: Checksums,
 }
 
 impl<'a> MPart<'a> {
     pub fn from(r: &'a mut impl Read, b: &[u8]) -> Self {
         MPart {
-            reader: r,
-            boundary: b.to_vec(),
-            buffer: [0_u8; 4096],
-            bytes_read: 0,
-            slice_start: 0,
-            slice_end: 0,
-            first: true,
-            last: false,
+            multipart: MultipartReader::new(r as &mut dyn Read, b),
+            checksums: Vec::new(),
+            storage: Box::new(DirStorage::new(
+                data::get_attachment_dir(),
+                CollisionPolicy::UniqueSuffix,
+            )),
+            max_field_len: data::MAX_FIELD_LEN,
+            error: None,
         }
     }
 
+    /// Sets a storage of files bigger than `CHUNK_THRESHOLD`, they are stored in `ATTACH_DIR`
+    /// by default
+    pub fn with_storage(mut self, storage: impl UploadStorage + 'static) -> Self {
+        self.storage = Box::new(storage);
+        self
+    }
+
+    /// Sets a limit of a part without a file name in bytes, a longer part is skipped,
+    /// `MAX_FIELD_LEN` is used by default
+    pub fn with_max_field_len(mut self, limit: usize) -> Self {
+        self.max_field_len = limit;
+        self
+    }
+
+    /// Sets digests calculated while parts are read
+    ///
+    /// A part not matching its `Content-MD5`, `Digest` or `Repr-Digest` header is skipped anyway.
+    pub fn with_checksums(mut self, kinds: &[ChecksumKind]) -> Self {
+        self.checksums = kinds.to_vec();
+        self
+    }
+
     pub fn consumed(&self) -> usize {
-        self.bytes_read
+        self.multipart.consumed()
     }
 
-    fn next_byte(&mut self) -> Option<u8> {
-        self.slice_start += 1;
-        if self.slice_start >= self.slice_end {
-            let Ok(len) = self.reader.read(&mut self.buffer) else {
-                return None; //Err(io::Error::new(ErrorKind::UnexpectedEof, "Failure - eof"))
+    /// Returns an error which ended the parts, `None` when all parts were read
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl MPart<'_> {
+    /// Reads the next part skipping ones not matching their digests
+    fn read_part(&mut self) -> io::Result<Option<Part>> {
+        loop {
+            let Some(part) = self.multipart.next_part()? else {
+                return Ok(None);
+            };
+            let headers = part.headers().clone();
+            let mut part = ChecksumReader::new(part, &self.checksums, &headers);
+            let mut chunk_content = Vec::with_capacity(ANTICIPATED_PART_SIZE);
+            let first_len = match headers.filename {
+                Some(_) => CHUNK_THRESHOLD,
+                None => self.max_field_len.saturating_add(1),
+            };
+            (&mut part)
+                .take(first_len as u64)
+                .read_to_end(&mut chunk_content)?;
+            let spill = match headers.filename {
+                // the rest of a big file goes directly to the storage
+                Some(_) if chunk_content.len() == CHUNK_THRESHOLD => {
+                    let mut sink = self.storage.begin(&headers)?;
+                    if let Err(err) = sink
+                        .write_all(&chunk_content)
+                        .and_then(|()| io::copy(&mut part, &mut sink))
+                    {
+                        sink.abort();
+                        return Err(err);
+                    }
+                    Some(sink)
+                }
+                Some(_) => {
+                    part.read_to_end(&mut chunk_content)?;
+                    None
+                }
+                // the rest of a long field is skipped by the next part
+                None if chunk_content.len() > self.max_field_len => {
+                    eprintln! {"error: the part {} skipped <= longer than {} bytes", headers.name, self.max_field_len};
+                    continue;
+                }
+                None => None,
             };
-            if len == 0 {
-                return None; // Err(io::Error::new(ErrorKind::UnexpectedEof, "Failure - eof"))
+            let checksums = part.finish();
+            if let Err(err) = checksums.verify(&headers) {
+                eprintln! {"error: the part {} skipped <= {}", headers.name, err.reason};
+                if let Some(sink) = spill {
+                    sink.abort()
+                }
+                continue;
             }
-            self.slice_start = 0;
-            self.slice_end = len;
-            self.bytes_read += len;
+            let content = match spill {
+                Some(sink) => Storage::Disk(sink.commit(&checksums)?),
+                None => Storage::Mem(chunk_content),
+            };
+            return Ok(Some(Part {
+                content_type: headers.content_type.or(Some("text/plain".to_string())),
+                content_name: headers.name,
+                total_read_ammount: self.multipart.consumed(),
+                content_filename: headers.filename.as_deref().map(sanitize_filename),
+                original_filename: headers.filename,
+                content,
+                checksums,
+            }));
         }
-        Some(self.buffer[self.slice_start])
     }
+}
 
-    fn parse_name_line(&mut self) -> Option<(String, Option<String>)> {
-        let mut temp_stor = Vec::with_capacity(256);
-        loop {
-            let b = self.next_byte()?;
-
-            if b == 0x0d {
-                let b2 = self.next_byte()?;
-                if b2 == 0x0a {
-                    if temp_stor.is_empty() {
-                        return None;
-                    } else {
-                        let line = String::from_utf8(temp_stor).ok()?;
-                        //eprintln!{"dispt {line}"}
-                        let line = adjust_to_pattern(line, "Ullllll-Ullllllllll: llll-llll; llll");
-                        match line.strip_prefix("Content-Disposition: form-data; name=\"") {
-                            Some(line) => {
-                                let Some((name, file)) = line.split_once('"') else {
-                                    return Some((String::from(""), None));
-                                };
-                                if !file.is_empty() {
-                                    let file = adjust_to_pattern(file.to_string(), "; llllllll");
-                                    if let Some(file) = file.strip_prefix("; filename=\"") {
-                                        let Some((file, _)) = file.split_once('"') else {
-                                            return Some((String::from(name), None));
-                                        };
-                                        return Some((
-                                            String::from(name),
-                                            Some(String::from(file)),
-                                        ));
-                                    }
-                                }
-                                return Some((String::from(name), None));
-                            }
-                            _ => return None, //Err(io::Error::new(ErrorKind::Other, "Failure - no content disposition"))
+impl Iterator for MPart<'_> {
+    type Item = Part;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() {
+            return None;
+        }
+        self.read_part().unwrap_or_else(|err| {
+            self.error = Some(err);
+            None
+        })
+    }
+}
+
+/// Headers of a part of `multipart/form-data`
+#[derive(Debug, Clone, Default)]
+pub struct PartHeaders {
+    /// a field name of `Content-Disposition`
+    pub name: String,
+    /// a file name of `Content-Disposition` as sent by the client
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    /// all headers in their order, names are in lowercase
+    pub headers: Vec<(String, String)>,
+}
+
+impl PartHeaders {
+    /// Returns a value of the header, the name is case insensitive
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, val)| val.as_str())
+    }
+
+    /// Parses a header block, a header can be folded over several lines
+    fn parse(block: &[u8]) -> io::Result<Self> {
+        let mut res = PartHeaders::default();
+        for line in block.split(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line));
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with([' ', '\t'])
+                && let Some((_, val)) = res.headers.last_mut()
+            {
+                val.push(' ');
+                val.push_str(line.trim());
+                continue;
+            }
+            let Some((name, val)) = line.split_once(':') else {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format! {"malformed part header: {line}"},
+                ));
+            };
+            res.headers
+                .push((name.trim().to_ascii_lowercase(), val.trim().to_string()))
+        }
+        for (name, val) in &res.headers {
+            match name.as_str() {
+                "content-disposition" => {
+                    for (key, param) in header_params(val) {
+                        match key.as_str() {
+                            "name" => res.name = param,
+                            "filename" => res.filename = Some(param),
+                            _ => (),
                         }
                     }
-                } else {
-                    if b2 == 0x0d {
-                        // should be failure
-                        return None; //Ok((String::from(""), None))
-                    }
-                    temp_stor.push(b2)
                 }
-            } else {
-                temp_stor.push(b)
+                "content-type" => res.content_type = Some(val.clone()),
+                _ => (),
             }
         }
+        Ok(res)
     }
+}
 
-    fn parse_type_line(&mut self) -> Option<String> {
-        let mut temp_stor = Vec::with_capacity(256);
-        loop {
-            let b = self.next_byte()?;
-
-            if b == 0x0d {
-                let b2 = self.next_byte()?;
-                if b2 == 0x0a {
-                    if temp_stor.is_empty() {
-                        return None;
-                    } else {
-                        return match String::from_utf8(temp_stor) {
-                            Ok(res) => {
-                                let res = adjust_to_pattern(res, "Ullllll-Ulll:");
-                                match res.strip_prefix("Content-Type: ") {
-                                    Some(res) => Some(res.to_string()), // can be parsed further for ;charset=UTF-8
-                                    _ => Some(String::new()),
-                                }
-                            }
-                            _ => Some("".to_string()),
-                        };
-                    }
-                } else {
-                    temp_stor.push(b2)
+/// Parses parameters following the first token of a header value
+///
+/// Names are returned in lowercase. A value can be a token or a quoted string, where `\` escapes
+/// `"` and `\`, other backslashes are kept since old browsers send Windows paths as they are.
+/// Extended values of RFC 2231 and RFC 5987 as `filename*=UTF-8''na%C3%AFve.txt` are decoded,
+/// including continuations as `filename*0*=` and `filename*1=`. An extended value replaces
+/// a plain value of the same name.
+///
+/// # Examples
+/// ```
+/// let params = header_params(r#"form-data; filename="a.txt"; name=f; filename*=UTF-8''%C3%A9t%C3%A9.txt"#);
+/// assert_eq!(params[0], ("filename".to_string(), "été.txt".to_string()));
+/// assert_eq!(params[1], ("name".to_string(), "f".to_string()));
+/// ```
+pub fn header_params(val: &str) -> Vec<(String, String)> {
+    let mut raw = Vec::new();
+    let mut rest = match val.split_once(';') {
+        Some((_, params)) => params,
+        None => return Vec::new(),
+    };
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        let Some((key, tail)) = rest.split_once('=') else {
+            break;
+        };
+        let tail = tail.trim_start();
+        let (param, tail) = match tail.strip_prefix('"') {
+            Some(quoted) => unquote(quoted),
+            None => {
+                let (param, tail) = tail.split_once(';').unwrap_or((tail, ""));
+                (param.trim_end().to_string(), tail)
+            }
+        };
+        rest = tail;
+        let key = key.trim().to_ascii_lowercase();
+        let (key, extended) = match key.strip_suffix('*') {
+            Some(key) => (key.to_string(), true),
+            None => (key, false),
+        };
+        let (key, section) = match key.split_once('*') {
+            Some((key, section)) => match section.parse() {
+                Ok(section) => (key.to_string(), Some(section)),
+                Err(_) => continue,
+            },
+            None => (key, None),
+        };
+        raw.push(RawParam {
+            name: key,
+            section,
+            extended,
+            value: param,
+        })
+    }
+    let mut names: Vec<&str> = Vec::new();
+    for param in &raw {
+        if !names.contains(&param.name.as_str()) {
+            names.push(&param.name)
+        }
+    }
+    let mut res = Vec::with_capacity(names.len());
+    for name in names {
+        let mut sections: Vec<_> = raw
+            .iter()
+            .filter(|param| param.name == name && param.section.is_some())
+            .collect();
+        sections.sort_by_key(|param| param.section);
+        let single = |extended| {
+            raw.iter().find(|param| {
+                param.name == name && param.section.is_none() && param.extended == extended
+            })
+        };
+        let val = decode_continuations(&sections)
+            .or_else(|| single(true).and_then(|param| decode_extended(&param.value)))
+            .or_else(|| single(false).map(|param| param.value.clone()));
+        if let Some(val) = val {
+            res.push((name.to_string(), val))
+        }
+    }
+    res
+}
+
+/// A parameter of a header as it's written
+struct RawParam {
+    name: String,
+    /// a section number of a continuation
+    section: Option<u32>,
+    /// the value is percent encoded, the first or a single section has a charset
+    extended: bool,
+    value: String,
+}
+
+/// Reads a quoted string after the opening quote, the string and the rest after it are returned
+fn unquote(quoted: &str) -> (String, &str) {
+    let mut res = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '"' => return (res, &quoted[pos + 1..]),
+            '\\' if quoted[pos + 1..].starts_with(['"', '\\']) => {
+                if let Some((_, c)) = chars.next() {
+                    res.push(c)
                 }
-            } else {
-                temp_stor.push(b)
             }
+            c => res.push(c),
         }
     }
+    (res, "")
 }
 
-impl Iterator for MPart<'_> {
-    type Item = Part;
+/// Decodes `charset'language'percent-encoded` of RFC 5987
+fn decode_extended(param: &str) -> Option<String> {
+    let mut parts = param.splitn(3, '\'');
+    let (Some(charset), Some(_language), Some(encoded)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    Some(decode_charset(charset, &percent_bytes(encoded)?))
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.last {
-            return None;
+/// Joins sorted sections of RFC 2231, only the first extended section has a charset
+///
+/// `None` is returned without the section 0, so a plain value is used.
+fn decode_continuations(sections: &[&RawParam]) -> Option<String> {
+    if sections.first()?.section != Some(0) {
+        return None;
+    }
+    let mut charset = None;
+    let mut bytes = Vec::new();
+    for (i, section) in sections.iter().enumerate() {
+        if section.section != Some(i as u32) {
+            // a missing section
+            break;
+        }
+        let param = &section.value;
+        match (i, section.extended) {
+            (0, true) => {
+                let mut parts = param.splitn(3, '\'');
+                let (Some(set), Some(_), Some(encoded)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return None;
+                };
+                charset = Some(set);
+                bytes.extend(percent_bytes(encoded)?)
+            }
+            (_, true) => bytes.extend(percent_bytes(param)?),
+            (_, false) => bytes.extend_from_slice(param.as_bytes()),
+        }
+    }
+    Some(decode_charset(charset.unwrap_or("UTF-8"), &bytes))
+}
+
+fn percent_bytes(encoded: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            res.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?)
+        } else {
+            res.push(b)
         }
-        if self.first {
-            let b = self.next_byte()?;
-            let b2 = self.next_byte()?;
-            if b == 0x2D && b2 == 0x2D {
-                for i in 0..self.boundary.len() {
-                    if self.next_byte()? != self.boundary[i] {
-                        return None;
+    }
+    Some(res)
+}
+
+/// Decodes by the named charset, UTF-8 is used for an unknown one
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    Charset::from_label(charset)
+        .unwrap_or_default()
+        .decode(bytes, DecodePolicy::Lossy)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, PartialEq)]
+enum ReaderState {
+    /// reading a preamble or a part body
+    Body,
+    /// a delimiter was consumed
+    Boundary,
+    Done,
+}
+
+/// A streaming reader of `multipart/form-data`
+///
+/// Parts are returned one by one with their headers and a reader of the body,
+/// so a body of any size can be copied to its destination using a small buffer.
+/// An unread rest of a part is skipped by the next call of `next_part`.
+///
+/// # Examples
+/// ```
+/// let mut multipart = MultipartReader::new(io::stdin().take(length), boundary.as_bytes());
+/// while let Some(mut part) = multipart.next_part()? {
+///     if part.headers().filename.is_some() {
+///         io::copy(&mut part, &mut File::create(upload_path)?)?;
+///     }
+/// }
+/// ```
+pub struct MultipartReader<R: Read> {
+    reader: R,
+    /// `CRLF--boundary`
+    delimiter: Finder,
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize,
+    /// buffered data before it have no delimiter
+    clear: usize,
+    state: ReaderState,
+    consumed: usize,
+}
+
+impl<R: Read> MultipartReader<R> {
+    pub fn new(reader: R, boundary: &[u8]) -> Self {
+        let mut buffer = vec![0; BUF_LEN];
+        // the first delimiter can go without a line break
+        buffer[..2].copy_from_slice(b"\r\n");
+        MultipartReader {
+            reader,
+            delimiter: Finder::new(&[b"\r\n--", boundary].concat()),
+            buffer,
+            start: 0,
+            end: 2,
+            clear: 0,
+            state: ReaderState::Body,
+            consumed: 0,
+        }
+    }
+
+    /// Returns the number of bytes read from the source
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Returns the next part, or `None` after the closing delimiter
+    pub fn next_part(&mut self) -> io::Result<Option<PartReader<'_, R>>> {
+        let mut skipped = [0_u8; BUF_LEN];
+        while self.state == ReaderState::Body {
+            self.read_body(&mut skipped)?;
+        }
+        if self.state == ReaderState::Done || !self.ensure(2)? {
+            return Ok(None);
+        }
+        if self.buffer[self.start..].starts_with(b"--") {
+            self.state = ReaderState::Done;
+            return Ok(None);
+        }
+        // transport padding can follow the boundary
+        loop {
+            if !self.ensure(2)? {
+                return Err(unexpected_eof());
+            }
+            match self.buffer[self.start] {
+                b' ' | b'\t' => self.start += 1,
+                _ => break,
+            }
+        }
+        if !self.buffer[self.start..self.end].starts_with(b"\r\n") {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "malformed multipart boundary line",
+            ));
+        }
+        self.start += 2;
+        let headers = self.read_headers()?;
+        self.state = ReaderState::Body;
+        self.clear = self.start;
+        Ok(Some(PartReader::new(self, headers)))
+    }
+
+    /// Reads a header block including the empty line
+    fn read_headers(&mut self) -> io::Result<PartHeaders> {
+        let mut block = Vec::new();
+        loop {
+            let data = &self.buffer[self.start..self.end];
+            if block.is_empty() && data.starts_with(b"\r\n") {
+                self.start += 2;
+                return PartHeaders::parse(&block);
+            }
+            match find(data, b"\r\n\r\n") {
+                Some(pos) => {
+                    block.extend_from_slice(&data[..pos]);
+                    self.start += pos + 4;
+                    return PartHeaders::parse(&block);
+                }
+                None => {
+                    // keep a possible start of the end of the block
+                    let len = data.len().saturating_sub(3);
+                    block.extend_from_slice(&data[..len]);
+                    self.start += len;
+                    if block.len() > MAX_HEADERS_LEN {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            "too long part headers",
+                        ));
+                    }
+                    if !self.fill()? {
+                        return Err(unexpected_eof());
                     }
                 }
-            } else {
-                return None;
             }
-            let b = self.next_byte()?;
-            let b2 = self.next_byte()?;
-            if b != 0x0d || b2 != 0x0a {
-                // check for last
-                if b == 0x2D && b2 == 0x2D {
-                    let b = self.next_byte()?;
-                    let b2 = self.next_byte()?;
-                    if b == 0x0d && b2 == 0x0a {
-                        return None;
+        }
+    }
+
+    /// Reads a body until the delimiter, 0 is returned when it's reached
+    fn read_body(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.state == ReaderState::Body && !out.is_empty() {
+            if self.start < self.clear {
+                let len = out.len().min(self.clear - self.start);
+                out[..len].copy_from_slice(&self.buffer[self.start..self.start + len]);
+                self.start += len;
+                return Ok(len);
+            }
+            match self.delimiter.find(&self.buffer[self.start..self.end]) {
+                Some(0) => {
+                    self.start += self.delimiter.len();
+                    self.state = ReaderState::Boundary
+                }
+                Some(pos) => self.clear = self.start + pos,
+                None => {
+                    // a tail can be a beginning of the delimiter
+                    let clear = self.end.saturating_sub(self.delimiter.len() - 1);
+                    if clear > self.start {
+                        self.clear = clear
+                    } else if !self.fill()? {
+                        return Err(unexpected_eof());
                     }
                 }
             }
+        }
+        Ok(0)
+    }
+
+    /// Makes sure that the buffer has at least `len` bytes, `false` is returned on the end of data
+    fn ensure(&mut self, len: usize) -> io::Result<bool> {
+        while self.end - self.start < len {
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 
-            self.first = false
+    /// Moves the buffered data to the beginning and reads more, `false` is returned on the end of data
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.start > 0 {
+            self.buffer.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.clear = self.clear.saturating_sub(self.start);
+            self.start = 0
         }
-        // read and parse line after boundary
-        let (name, filename) = self.parse_name_line()?;
-        let content_type = match self.parse_type_line() {
-            None => Some("text/plain".to_string()),
-            Some(text) => {
-                // read empty line
-                let b = self.next_byte()?;
-                let b2 = self.next_byte()?;
-                if b != 0x0d || b2 != 0x0a {
-                    // TODO can be one more line as
-                    // content-transfer-encoding: quoted-printable
-                    return None;
-                }
-                Some(text.to_string())
+        let len = loop {
+            match self.reader.read(&mut self.buffer[self.end..]) {
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                res => break res?,
             }
         };
-        //eprintln!{"read content of {name} and type {content_type:?}"}
-        let mut chunk_content = Vec::with_capacity(ANTICIPATED_PART_SIZE);
-        let mut temp_stor = Vec::with_capacity(ANTICIPATED_PART_SIZE);
-        let mut storage_file = None;
-        loop {
-            let b = self.next_byte()?;
-            if b == 0x2D {
-                let b2 = self.next_byte()?;
-                if b2 == 0x2d {
-                    temp_stor.clear();
-                    temp_stor.push(b);
-                    temp_stor.push(b);
-                    for i in 0..self.boundary.len() {
-                        let bn = self.next_byte()?;
-                        if bn != self.boundary[i] {
-                            chunk_content.append(&mut temp_stor);
-                            chunk_content.push(bn);
-                            if chunk_content.len() > CHUNK_THRESHOLD
-                                && let Some(ref filename) = filename
-                            {
-                                if storage_file.is_none() {
-                                    let f = OpenOptions::new()
-                                        .create(true)
-                                        .write(true)
-                                        .truncate(true)
-                                        .open(get_attachment_file(filename))
-                                        .ok()?; // make some weird extension as .~part
-                                    storage_file = Some(f)
-                                }
-                                eprintln!("writing {} of chunk", chunk_content.len());
-                                storage_file.as_ref()?.write_all(&chunk_content).ok()?;
-                                chunk_content.clear()
-                            }
-                            break;
+        self.end += len;
+        self.consumed += len;
+        Ok(len > 0)
+    }
+}
+
+/// A part of `MultipartReader`, it reads the part body
+///
+/// A body sent with `Content-Transfer-Encoding` `base64` or `quoted-printable` is decoded.
+pub struct PartReader<'a, R: Read> {
+    headers: PartHeaders,
+    body: PartBody<'a, R>,
+}
+
+enum PartBody<'a, R: Read> {
+    Raw(RawBody<'a, R>),
+    Base64(Base64Reader<RawBody<'a, R>>),
+    QuotedPrintable(QuotedPrintableReader<RawBody<'a, R>>),
+}
+
+impl<'a, R: Read> PartReader<'a, R> {
+    fn new(multipart: &'a mut MultipartReader<R>, headers: PartHeaders) -> Self {
+        let raw = RawBody(multipart);
+        let encoding = headers
+            .get("content-transfer-encoding")
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let body = match encoding.as_str() {
+            "base64" => PartBody::Base64(Base64::STANDARD.lenient().decoder(raw)),
+            "quoted-printable" => PartBody::QuotedPrintable(QuotedPrintableReader::new(raw)),
+            // 7bit, 8bit and binary
+            _ => PartBody::Raw(raw),
+        };
+        PartReader { headers, body }
+    }
+
+    pub fn headers(&self) -> &PartHeaders {
+        &self.headers
+    }
+}
+
+impl<R: Read> Read for PartReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.body {
+            PartBody::Raw(raw) => raw.read(buf),
+            PartBody::Base64(decoder) => decoder.read(buf),
+            PartBody::QuotedPrintable(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// A body of a part as it's sent
+struct RawBody<'a, R: Read>(&'a mut MultipartReader<R>);
+
+impl<R: Read> Read for RawBody<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read_body(buf)
+    }
+}
+
+/// A streaming decoder of the quoted-printable encoding of RFC 2045
+///
+/// Soft line breaks and white spaces at ends of lines are removed,
+/// a `=` not followed by two hex digits is kept as it is.
+struct QuotedPrintableReader<R: Read> {
+    inner: R,
+    input: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    /// runs of white spaces taken from the input, written out or dropped by what follows them
+    blanks: VecDeque<(u8, usize)>,
+    flush: bool,
+}
+
+impl<R: Read> QuotedPrintableReader<R> {
+    fn new(inner: R) -> Self {
+        QuotedPrintableReader {
+            inner,
+            input: Vec::with_capacity(BUF_LEN),
+            pos: 0,
+            eof: false,
+            blanks: VecDeque::new(),
+            flush: false,
+        }
+    }
+
+    /// Decodes the buffered input, an incomplete sequence at the end waits for more input
+    fn decode(&mut self, out: &mut [u8]) -> usize {
+        let input = &self.input;
+        let mut len = 0;
+        let mut pos = self.pos;
+        while len < out.len() {
+            if self.flush {
+                match self.blanks.front_mut() {
+                    Some((b, count)) => {
+                        let n = (*count).min(out.len() - len);
+                        out[len..len + n].fill(*b);
+                        len += n;
+                        *count -= n;
+                        if *count == 0 {
+                            self.blanks.pop_front();
                         }
-                        temp_stor.push(bn)
                     }
-                    if temp_stor.len() == self.boundary.len() + 2 {
-                        let b = self.next_byte()?;
-                        let b2 = self.next_byte()?;
-
-                        if b == 0x0d && b2 == 0x0a || b == 0x2D && b2 == 0x2D {
-                            if b == 0x2D && b2 == 0x2D {
-                                let b = self.next_byte()?;
-                                let b2 = self.next_byte()?;
-                                // check they are 0d 0a
-                                if b != 0x0d || b2 != 0x0a {
-                                    //eprintln!{"no end line"}
-                                    return None;
-                                }
-                                self.last = true
-                            }
-                            // remove \r\n
-                            chunk_content.truncate(chunk_content.len() - 2); // 2 times pop
-                            if let Some(ref mut f) = storage_file {
-                                f.write_all(&chunk_content).ok()?;
-                            }
-                            eprintln!("final read {} for {filename:?}", self.bytes_read);
-                            return Some(Part {
-                                content_type,
-                                content_name: name,
-                                total_read_ammount: self.bytes_read,
-                                content: if let Some(ref mut _f) = storage_file {
-                                    Storage::Disk(get_attachment_file(&filename.clone().unwrap())) // potential inconsistency in name of a temporary file
-                                } else {
-                                    Storage::Mem(chunk_content)
-                                },
-                                content_filename: filename,
-                            });
-                        } else {
-                            //eprintln!{"tail after sep bndry -- {b} {b2}"}
+                    None => self.flush = false,
+                }
+                continue;
+            }
+            if pos == input.len() {
+                if self.eof {
+                    self.blanks.clear()
+                }
+                break;
+            }
+            let rest = &input[pos..];
+            if !self.blanks.is_empty() && !matches!(rest[0], b' ' | b'\t') {
+                if rest == b"\r" && !self.eof {
+                    break;
+                }
+                if rest.starts_with(b"\r\n") || rest.starts_with(b"\n") {
+                    self.blanks.clear()
+                } else {
+                    self.flush = true;
+                    continue;
+                }
+            }
+            match rest[0] {
+                b'=' => {
+                    if rest.len() < 3 && !self.eof && !rest.starts_with(b"=\n") {
+                        break;
+                    }
+                    if rest.starts_with(b"=\r\n") {
+                        pos += 3;
+                        continue;
+                    }
+                    // the line break of the last soft break belongs to the delimiter
+                    if rest.starts_with(b"=\n") || rest == b"=" {
+                        pos += rest.len().min(2);
+                        continue;
+                    }
+                    match rest
+                        .get(1..3)
+                        .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+                    {
+                        Some(b) => {
+                            out[len] = b;
+                            pos += 3
+                        }
+                        None => {
+                            out[len] = b'=';
+                            pos += 1
                         }
-                    } else {
-                        //eprintln!{"boundary {} found {}",self.boundary.len(), temp_stor.len()}
                     }
-                } else {
-                    chunk_content.push(b);
-                    chunk_content.push(b2)
                 }
-            } else {
-                chunk_content.push(b)
+                b @ (b' ' | b'\t') => {
+                    let count = rest.iter().take_while(|&&next| next == b).count();
+                    match self.blanks.back_mut() {
+                        Some((last, run)) if *last == b => *run += count,
+                        _ => self.blanks.push_back((b, count)),
+                    }
+                    pos += count;
+                    continue;
+                }
+                b => {
+                    out[len] = b;
+                    pos += 1
+                }
             }
+            len += 1
         }
+        self.pos = pos;
+        len
     }
 }
 
-fn get_attachment_file(file_name: &str) -> PathBuf {
-    let file_name = file_name.to_owned() + ".~part";
-    let mut res = data::get_attachment_dir();
-    res.push(file_name);
-    res
+impl<R: Read> Read for QuotedPrintableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let len = self.decode(buf);
+            if len > 0 || (self.eof && self.pos == self.input.len()) {
+                return Ok(len);
+            }
+            self.input.drain(..self.pos);
+            self.pos = 0;
+            let start = self.input.len();
+            self.input.resize(start + BUF_LEN, 0);
+            let read = loop {
+                match self.inner.read(&mut self.input[start..]) {
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    res => break res,
+                }
+            };
+            match read {
+                Ok(len) => {
+                    self.input.truncate(start + len);
+                    self.eof = len == 0
+                }
+                Err(err) => {
+                    self.input.truncate(start);
+                    return Err(err);
+                }
+            }
+        }
+    }
 }
 
-fn adjust_to_pattern(mut s: String, pat: &str) -> String {
-    let content = unsafe { s.as_bytes_mut() };
-    for (i, c) in pat.chars().enumerate() {
-        match c {
-            'U' => {
-                if content[i] >= 97 && content[i] <= 122 {
-                    content[i] -= 32
-                }
+/// Boyer-Moore-Horspool search of a fixed pattern
+///
+/// A mismatch shifts the pattern by up to its length, so a long boundary is found
+/// by looking at a small part of the data.
+struct Finder {
+    needle: Vec<u8>,
+    /// a shift by the last byte of a window
+    skip: [usize; 256],
+}
+
+impl Finder {
+    fn new(needle: &[u8]) -> Self {
+        let mut skip = [needle.len(); 256];
+        let last = needle.len() - 1;
+        for (i, &b) in needle[..last].iter().enumerate() {
+            skip[b as usize] = last - i
+        }
+        Finder {
+            needle: needle.to_vec(),
+            skip,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.needle.len()
+    }
+
+    fn find(&self, haystack: &[u8]) -> Option<usize> {
+        let last = self.needle.len() - 1;
+        let mut pos = 0;
+        while pos + last < haystack.len() {
+            let b = haystack[pos + last];
+            if b == self.needle[last] && haystack[pos..pos + last] == self.needle[..last] {
+                return Some(pos);
             }
-            'l' if content[i] >= 65 && content[i] <= 90 => content[i] += 32,
-            _ => (),
+            pos += self.skip[b as usize]
         }
+        None
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(
+        ErrorKind::UnexpectedEof,
+        "multipart data ended without the closing boundary",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mpart_errors() {
+        let body = "--B\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n\
+            --B\r\nContent-Disposition: form-data; name=\"b\"\r\n\r\n2";
+        let mut input = body.as_bytes();
+        let mut parts = MPart::from(&mut input, b"B");
+        assert_eq!(parts.next().unwrap().content_name, "a");
+        assert!(parts.error().is_none());
+        assert!(parts.next().is_none());
+        assert_eq!(
+            parts.error().map(io::Error::kind),
+            Some(ErrorKind::UnexpectedEof)
+        );
+        assert!(parts.next().is_none());
+    }
+
+    #[test]
+    fn continuations() {
+        let params = header_params(r#"form-data; name="a"; filename*1="x""#);
+        assert_eq!(params, [("name".to_string(), "a".to_string())]);
+        let params = header_params(r#"form-data; filename="a.txt"; filename*1="x""#);
+        assert_eq!(params, [("filename".to_string(), "a.txt".to_string())]);
+        let params =
+            header_params(r#"form-data; filename*1="b.txt"; filename*0="a"; filename*3="c""#);
+        assert_eq!(params, [("filename".to_string(), "ab.txt".to_string())]);
+    }
+
+    #[test]
+    fn quoted_printable_blanks() {
+        let decode = |input: &[u8]| {
+            let mut res = Vec::new();
+            QuotedPrintableReader::new(input)
+                .read_to_end(&mut res)
+                .unwrap();
+            res
+        };
+        assert_eq!(decode(b"a \t b=\r\nc  \r\nd\t\n=41 "), b"a \t bc\r\nd\nA");
+        let mut input = vec![b' '; 4 * CHUNK_THRESHOLD];
+        input.extend_from_slice(b"\t\t=\r\n");
+        let res = decode(&input);
+        assert_eq!(res.len(), input.len() - 3);
+        assert!(res[..res.len() - 2].iter().all(|&b| b == b' '));
+        input.truncate(input.len() - 5);
+        input.extend_from_slice(b"\r\nx");
+        assert_eq!(decode(&input), b"\r\nx");
+    }
+
+    #[test]
+    fn field_limit() {
+        let body = "--B\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n12345\r\n\
+            --B\r\nContent-Disposition: form-data; name=\"b\"\r\n\r\n1234\r\n--B--\r\n";
+        let mut input = body.as_bytes();
+        let mut parts = MPart::from(&mut input, b"B").with_max_field_len(4);
+        let part = parts.next().unwrap();
+        assert_eq!(part.content_name, "b");
+        assert!(matches!(part.content, Storage::Mem(content) if content == b"1234"));
+        assert!(parts.next().is_none());
+        assert!(parts.error().is_none());
+    }
+
+    #[test]
+    fn big_file_storage() {
+        let content = "x".repeat(CHUNK_THRESHOLD + 10);
+        let body = format! {"--B\r\nContent-Disposition: form-data; name=\"f\"; filename=\"big.bin\"\r\n\r\n{content}\r\n--B--\r\n"};
+        let files = crate::MemoryStorage::default();
+        let mut input = body.as_bytes();
+        let parts: Vec<_> = MPart::from(&mut input, b"B")
+            .with_storage(files.clone())
+            .collect();
+        let Storage::Disk(location) = &parts[0].content else {
+            panic!("a big file in memory")
+        };
+        assert_eq!(
+            files.get(location).map(|file| file.len()),
+            Some(content.len())
+        );
     }
-    s
 }
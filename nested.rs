@@ -0,0 +1,339 @@
+use crate::json_encode;
+use std::collections::HashMap;
+
+/// names nested deeper are used as plain keys
+const MAX_DEPTH: usize = 32;
+
+/// A structured view of parameters using the bracket notation
+///
+/// Names as `user[address][city]` produce nested maps, and `items[]` appends to a list.
+/// Maps having numeric keys `0` to `n - 1` as `rows[0][qty]`, `rows[1][qty]` become lists
+/// ordered by the index. Other numeric keys as `rows[3][qty]` stay map keys, so every element
+/// keeps its index for `path`. A name repeated without brackets produces a list of values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamTree {
+    Value(String),
+    List(Vec<ParamTree>),
+    /// keys are kept in the order of their first appearance
+    Map(Vec<(String, ParamTree)>),
+}
+
+impl ParamTree {
+    /// Builds a tree from (name, value) pairs
+    ///
+    /// Entries conflicting with already built structure, as `a[b]=1` after `a=2`, are ignored.
+    /// Names with unbalanced brackets or more than 32 bracket levels are used as plain keys.
+    ///
+    /// # Examples
+    /// ```
+    /// let tree = ParamTree::from_pairs([("user[name]", "Joe"), ("items[]", "1"), ("items[]", "2")]);
+    /// assert_eq!(tree.to_json(), r#"{"user":{"name":"Joe"},"items":["1","2"]}"#);
+    /// ```
+    pub fn from_pairs<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut root = Node::Map(Vec::new(), HashMap::new());
+        for (name, val) in pairs {
+            let keys = split_name(name);
+            insert(&mut root, &keys, val.to_string())
+        }
+        let mut res = root.into_tree();
+        // the root stays a map even for numeric names
+        if let ParamTree::Map(ref mut entries) = res {
+            entries.iter_mut().for_each(|(_, val)| normalize(val))
+        }
+        res
+    }
+
+    /// Returns a child of a map with the given key
+    pub fn get(&self, key: &str) -> Option<&ParamTree> {
+        match self {
+            ParamTree::Map(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, val)| val),
+            _ => None,
+        }
+    }
+
+    /// Returns an element of a list with the given index
+    pub fn index(&self, i: usize) -> Option<&ParamTree> {
+        match self {
+            ParamTree::List(items) => items.get(i),
+            _ => None,
+        }
+    }
+
+    /// Walks the tree by keys, where a numeric key can select a list element
+    ///
+    /// # Examples
+    /// ```
+    /// let city = data.param_tree().path(&["user", "address", "city"]).and_then(ParamTree::as_str);
+    /// ```
+    pub fn path(&self, keys: &[&str]) -> Option<&ParamTree> {
+        keys.iter().try_fold(self, |node, key| match node {
+            ParamTree::List(_) => node.index(key.parse().ok()?),
+            _ => node.get(key),
+        })
+    }
+
+    /// Returns a value of a leaf
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ParamTree::Value(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Returns list elements, a single leaf or map is returned as a list of one element
+    pub fn as_list(&self) -> &[ParamTree] {
+        match self {
+            ParamTree::List(items) => items,
+            _ => std::slice::from_ref(self),
+        }
+    }
+
+    /// Serializes the tree in JSON where all leaf values are strings
+    pub fn to_json(&self) -> String {
+        let mut res = String::new();
+        self.write_json(&mut res);
+        res
+    }
+
+    fn write_json(&self, res: &mut String) {
+        match self {
+            ParamTree::Value(val) => {
+                res.push('"');
+                res.push_str(&json_encode(val));
+                res.push('"')
+            }
+            ParamTree::List(items) => {
+                res.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        res.push(',')
+                    }
+                    item.write_json(res)
+                }
+                res.push(']')
+            }
+            ParamTree::Map(entries) => {
+                res.push('{');
+                for (i, (key, val)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        res.push(',')
+                    }
+                    res.push('"');
+                    res.push_str(&json_encode(key));
+                    res.push_str("\":");
+                    val.write_json(res)
+                }
+                res.push('}')
+            }
+        }
+    }
+}
+
+fn split_name(name: &str) -> Vec<&str> {
+    let Some((base, mut rest)) = name.split_once('[') else {
+        return vec![name];
+    };
+    if base.is_empty() {
+        return vec![name];
+    }
+    let mut res = vec![base];
+    loop {
+        let Some((key, tail)) = rest.split_once(']') else {
+            return vec![name];
+        };
+        if key.contains('[') || res.len() > MAX_DEPTH {
+            return vec![name];
+        }
+        res.push(key);
+        if tail.is_empty() {
+            return res;
+        }
+        match tail.strip_prefix('[') {
+            Some(tail) => rest = tail,
+            None => return vec![name],
+        }
+    }
+}
+
+/// A tree being built, maps have an index of their keys, so a key is found at once
+enum Node {
+    Value(String),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>, HashMap<String, usize>),
+}
+
+impl Node {
+    fn into_tree(self) -> ParamTree {
+        match self {
+            Node::Value(val) => ParamTree::Value(val),
+            Node::List(items) => ParamTree::List(items.into_iter().map(Node::into_tree).collect()),
+            Node::Map(entries, _) => ParamTree::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, val)| (key, val.into_tree()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn container(key: &str) -> Node {
+    if key.is_empty() {
+        Node::List(Vec::new())
+    } else {
+        Node::Map(Vec::new(), HashMap::new())
+    }
+}
+
+fn insert(node: &mut Node, keys: &[&str], val: String) {
+    let Some((key, rest)) = keys.split_first() else {
+        return;
+    };
+    match node {
+        Node::List(items) if key.is_empty() => match rest.first() {
+            None => items.push(Node::Value(val)),
+            Some(next) => {
+                let mut child = container(next);
+                insert(&mut child, rest, val);
+                items.push(child)
+            }
+        },
+        Node::Map(entries, index) if !key.is_empty() => {
+            let pos = match index.get(*key) {
+                Some(&pos) => pos,
+                None => {
+                    index.insert(key.to_string(), entries.len());
+                    match rest.first() {
+                        None => return entries.push((key.to_string(), Node::Value(val))),
+                        Some(next) => {
+                            entries.push((key.to_string(), container(next)));
+                            entries.len() - 1
+                        }
+                    }
+                }
+            };
+            let child = &mut entries[pos].1;
+            if rest.is_empty() {
+                match child {
+                    Node::List(items)
+                        if items.iter().all(|item| matches!(item, Node::Value(_))) =>
+                    {
+                        items.push(Node::Value(val))
+                    }
+                    Node::Value(prev) => {
+                        *child =
+                            Node::List(vec![Node::Value(std::mem::take(prev)), Node::Value(val)])
+                    }
+                    _ => (),
+                }
+            } else {
+                insert(child, rest, val)
+            }
+        }
+        _ => (), // conflicting structure
+    }
+}
+
+fn normalize(node: &mut ParamTree) {
+    match node {
+        ParamTree::Value(_) => (),
+        ParamTree::List(items) => items.iter_mut().for_each(normalize),
+        ParamTree::Map(entries) => {
+            for (_, val) in entries.iter_mut() {
+                normalize(val)
+            }
+        }
+    }
+    // only dense indices make a list, so an index can't change or allocate a gap
+    if let ParamTree::Map(entries) = node
+        && !entries.is_empty()
+    {
+        let len = entries.len();
+        let mut slots: Vec<Option<ParamTree>> = vec![None; len];
+        let dense = entries.iter().all(|(key, _)| {
+            key.parse::<usize>()
+                .is_ok_and(|i| i < len && i.to_string() == *key)
+        });
+        if !dense {
+            return;
+        }
+        for (key, val) in std::mem::take(entries) {
+            slots[key.parse::<usize>().unwrap_or_default()] = Some(val)
+        }
+        // repeated keys are merged when inserted, so every slot is filled
+        *node = ParamTree::List(slots.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices() {
+        let tree = ParamTree::from_pairs([
+            ("rows[1][qty]", "2"),
+            ("rows[0][qty]", "1"),
+            ("cols[3][qty]", "5"),
+            ("cols[7][qty]", "6"),
+            ("ids[01]", "x"),
+            ("ids[1]", "y"),
+        ]);
+        assert_eq!(
+            tree.path(&["rows", "0", "qty"]).and_then(ParamTree::as_str),
+            Some("1")
+        );
+        assert_eq!(
+            tree.path(&["rows", "1", "qty"]).and_then(ParamTree::as_str),
+            Some("2")
+        );
+        assert_eq!(
+            tree.path(&["cols", "3", "qty"]).and_then(ParamTree::as_str),
+            Some("5")
+        );
+        assert_eq!(tree.path(&["cols", "0", "qty"]), None);
+        assert_eq!(
+            tree.path(&["ids", "01"]).and_then(ParamTree::as_str),
+            Some("x")
+        );
+        assert_eq!(
+            tree.to_json(),
+            r#"{"rows":[{"qty":"1"},{"qty":"2"}],"cols":{"3":{"qty":"5"},"7":{"qty":"6"}},"ids":{"01":"x","1":"y"}}"#
+        );
+    }
+
+    #[test]
+    fn many_keys() {
+        let names: Vec<_> = (0..100_000).map(|i| format! {"a[k{i}]"}).collect();
+        let tree = ParamTree::from_pairs(names.iter().map(|name| (name.as_str(), "1")));
+        assert_eq!(
+            tree.path(&["a", "k99999"]).and_then(ParamTree::as_str),
+            Some("1")
+        );
+        let ParamTree::Map(entries) = tree.get("a").unwrap() else {
+            panic!("not a map")
+        };
+        assert_eq!(entries.len(), 100_000);
+        assert_eq!(entries[0].0, "k0");
+    }
+
+    #[test]
+    fn deep_name() {
+        let name = format!("a{}", "[x]".repeat(50_000));
+        let tree = ParamTree::from_pairs([(name.as_str(), "1")]);
+        assert_eq!(tree.get(&name).and_then(ParamTree::as_str), Some("1"));
+        let name = format!("a{}", "[x]".repeat(MAX_DEPTH));
+        let tree = ParamTree::from_pairs([(name.as_str(), "1")]);
+        let path = vec!["x"; MAX_DEPTH];
+        assert_eq!(
+            tree.get("a")
+                .and_then(|a| a.path(&path))
+                .and_then(ParamTree::as_str),
+            Some("1")
+        );
+        assert!(tree.to_json().starts_with(r#"{"a":{"x":{"#));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{JsonValue, WebData, WebPage, util::remove_older};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::{self, ErrorKind, Read},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// a query string parameter telling an ID of an upload
+pub const UPLOAD_ID_PARAM: &str = "upload_id";
+/// a minimal interval between updates of a progress file
+const FILE_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_ID_LEN: usize = 64;
+
+/// A state of receiving a request body
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UploadProgress {
+    /// the number of received bytes
+    pub received: u64,
+    /// `CONTENT_LENGTH` when it's known
+    pub total: Option<u64>,
+    /// all data was received, or an error happened
+    pub done: bool,
+    pub error: Option<String>,
+}
+
+impl UploadProgress {
+    /// Returns the progress in percents, when the total is known
+    pub fn percent(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(100.0),
+            Some(total) => Some(self.received as f64 * 100.0 / total as f64),
+            None => None,
+        }
+    }
+
+    /// Returns the progress as a JSON object, the total is `null` when it's unknown
+    ///
+    /// # Examples
+    /// ```
+    /// let progress = UploadProgress { received: 512, total: Some(2048), ..Default::default() };
+    /// assert_eq!(progress.to_json().to_json(), r#"{"received":512,"total":2048,"percent":25,"done":false}"#);
+    /// ```
+    pub fn to_json(&self) -> JsonValue {
+        let mut members = vec![
+            ("received".to_string(), JsonValue::from(self.received)),
+            (
+                "total".to_string(),
+                self.total.map(JsonValue::from).unwrap_or(JsonValue::Null),
+            ),
+            (
+                "percent".to_string(),
+                self.percent()
+                    .map(|percent| JsonValue::from(percent.floor()))
+                    .unwrap_or(JsonValue::Null),
+            ),
+            ("done".to_string(), JsonValue::from(self.done)),
+        ];
+        if let Some(error) = &self.error {
+            members.push(("error".to_string(), JsonValue::from(error.as_str())))
+        }
+        JsonValue::Object(members)
+    }
+}
+
+/// A receiver of the progress of a request body
+///
+/// Any `FnMut(&UploadProgress)` is a sink too.
+pub trait ProgressSink {
+    /// It's called after every read of the body, and once with `done` at the end
+    fn update(&mut self, progress: &UploadProgress);
+}
+
+impl<F: FnMut(&UploadProgress)> ProgressSink for F {
+    fn update(&mut self, progress: &UploadProgress) {
+        self(progress)
+    }
+}
+
+/// A reader reporting the number of read bytes to a sink
+///
+/// # Examples
+/// ```
+/// let total = env::var("CONTENT_LENGTH")?.parse()?;
+/// let mut input = ProgressReader::new(io::stdin().take(total), Some(total), |progress: &UploadProgress| {
+///     eprintln! {"{:?}%", progress.percent()}
+/// });
+/// let mut multipart = MultipartReader::new(input, boundary.as_bytes());
+/// while let Some(part) = multipart.next_part()? { ... }
+/// ```
+pub struct ProgressReader<R: Read, S: ProgressSink> {
+    inner: R,
+    sink: S,
+    progress: UploadProgress,
+}
+
+impl<R: Read, S: ProgressSink> ProgressReader<R, S> {
+    pub fn new(inner: R, total: Option<u64>, sink: S) -> Self {
+        ProgressReader {
+            inner,
+            sink,
+            progress: UploadProgress {
+                total,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn progress(&self) -> &UploadProgress {
+        &self.progress
+    }
+
+    /// Reports an error of processing the body, the body can be read to the end already
+    ///
+    /// An error of reading is kept.
+    pub fn fail(&mut self, error: &str) {
+        if self.progress.error.is_none() {
+            self.progress.done = true;
+            self.progress.error = Some(error.to_string());
+            self.sink.update(&self.progress)
+        }
+    }
+}
+
+impl<R: Read, S: ProgressSink> Read for ProgressReader<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                if !self.progress.done {
+                    self.progress.done = true;
+                    self.sink.update(&self.progress)
+                }
+                Ok(0)
+            }
+            Ok(len) => {
+                self.progress.received += len as u64;
+                self.sink.update(&self.progress);
+                Ok(len)
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => Err(err),
+            Err(err) => {
+                self.progress.done = true;
+                self.progress.error = Some(err.to_string());
+                self.sink.update(&self.progress);
+                Err(err)
+            }
+        }
+    }
+}
+
+/// A sink writing the progress as a JSON file `<upload ID>.json` in a progress directory
+///
+/// The file is replaced atomically at most 4 times a second, so a reader never sees
+/// a partial file. Use `ProgressPage` to serve it to a browser.
+pub struct ProgressFile {
+    path: PathBuf,
+    last_write: Option<Instant>,
+}
+
+impl ProgressFile {
+    /// Creates a sink for the upload, the ID can have only letters, digits, `-` and `_`
+    pub fn new(dir: impl Into<PathBuf>, id: &str) -> io::Result<Self> {
+        Ok(ProgressFile {
+            path: progress_path(dir.into(), id)?,
+            last_write: None,
+        })
+    }
+
+    fn write(&self, progress: &UploadProgress) -> io::Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, progress.to_json().to_json())?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl ProgressSink for ProgressFile {
+    fn update(&mut self, progress: &UploadProgress) {
+        if !progress.done
+            && self
+                .last_write
+                .is_some_and(|last| last.elapsed() < FILE_UPDATE_INTERVAL)
+        {
+            return;
+        }
+        self.last_write = Some(Instant::now());
+        if let Err(err) = self.write(progress) {
+            eprintln! {"error: progress of {} not written <= {err}", self.path.display()}
+        }
+    }
+}
+
+/// Reads a progress written by `ProgressFile`
+///
+/// `NotFound` is returned when the upload hasn't started yet or the ID is unknown.
+pub fn read_progress(dir: impl Into<PathBuf>, id: &str) -> io::Result<UploadProgress> {
+    let json = fs::read_to_string(progress_path(dir.into(), id)?)?;
+    let json = JsonValue::parse(&json)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.reason))?;
+    let number = |name| {
+        json.get(name)
+            .and_then(JsonValue::as_f64)
+            .map(|val| val as u64)
+    };
+    Ok(UploadProgress {
+        received: number("received").unwrap_or_default(),
+        total: number("total"),
+        done: json
+            .get("done")
+            .and_then(JsonValue::as_bool)
+            .unwrap_or_default(),
+        error: json
+            .get("error")
+            .and_then(JsonValue::as_str)
+            .map(str::to_string),
+    })
+}
+
+/// Removes progress files older than the given age, it returns the number of removed files
+pub fn clean_progress(dir: impl Into<PathBuf>, max_age: Duration) -> io::Result<usize> {
+    remove_older(&dir.into(), max_age, |path| {
+        path.extension()
+            .is_some_and(|ext| ext == "json" || ext == "tmp")
+    })
+}
+
+fn progress_path(dir: PathBuf, id: &str) -> io::Result<PathBuf> {
+    if id.is_empty()
+        || id.len() > MAX_ID_LEN
+        || !id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format! {"invalid upload ID {id}"},
+        ));
+    }
+    Ok(dir.join(format! {"{id}.json"}))
+}
+
+/// A page responding the progress of an upload as JSON
+///
+/// The upload ID is taken from the `upload_id` query parameter, the same one that the form
+/// posting the upload has in its action URL. An upload which hasn't started yet has zero progress.
+///
+/// # Examples
+/// ```
+/// // the main of a CGI program polled by a browser as progress?upload_id=7f3a9c
+/// ProgressPage { dir: PathBuf::from("/var/tmp/progress") }.show()
+/// ```
+pub struct ProgressPage {
+    pub dir: PathBuf,
+}
+
+impl WebPage for ProgressPage {
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    fn main_load(&self) -> Result<String, Box<dyn Error>> {
+        let id = WebData::new()
+            .param(UPLOAD_ID_PARAM)
+            .ok_or("no upload ID")?;
+        let progress = match read_progress(&self.dir, &id) {
+            Err(err) if err.kind() == ErrorKind::NotFound => UploadProgress::default(),
+            res => res?,
+        };
+        Ok(progress.to_json().to_json())
+    }
+
+    fn get_extra(&self) -> Option<Vec<(String, String)>> {
+        Some(vec![("Cache-Control".to_string(), "no-store".to_string())])
+    }
+
+    fn apply_specific(&self, page_map: &mut HashMap<&str, String>) -> Result<(), Box<dyn Error>> {
+        // JSON isn't a template
+        page_map.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{Base64, to_hex};
+use std::{
+    fs::File,
+    io::{self, Read},
+    time::SystemTime,
+};
+
+/// Fills the buffer by random bytes of the OS CSPRNG
+///
+/// `getrandom(2)` is used on Linux x86_64 and aarch64, it doesn't need a file descriptor
+/// and works in a chroot. `/dev/urandom` is read on other systems or when the call fails before
+/// any byte is filled, e.g. it isn't available or is denied by a seccomp filter.
+pub fn fill_random(buf: &mut [u8]) -> io::Result<()> {
+    match getrandom(buf) {
+        Some(res) => res,
+        None => File::open("/dev/urandom")?.read_exact(buf),
+    }
+}
+
+/// Returns the given number of random bytes
+pub fn random_bytes(len: usize) -> io::Result<Vec<u8>> {
+    let mut res = vec![0; len];
+    fill_random(&mut res)?;
+    Ok(res)
+}
+
+/// Returns a hex token of the given number of random bytes, so the token has the twice length
+pub fn random_hex(len: usize) -> io::Result<String> {
+    Ok(to_hex(&random_bytes(len)?))
+}
+
+/// Returns a URL safe BASE64 token without padding of the given number of random bytes
+///
+/// The token can be put in a URL or a cookie without encoding.
+pub fn random_token(len: usize) -> io::Result<String> {
+    Ok(Base64::URL_SAFE_NO_PAD.encode(&random_bytes(len)?))
+}
+
+/// Returns a random UUID version 4 of RFC 9562 as a lowercase string
+///
+/// # Examples
+/// ```
+/// let id = uuid_v4()?; // like "919108f7-52d1-4320-9bac-f847db4148a8"
+/// assert_eq!(id.as_bytes()[14], b'4');
+/// ```
+pub fn uuid_v4() -> io::Result<String> {
+    let mut uuid = [0_u8; 16];
+    fill_random(&mut uuid)?;
+    Ok(format_uuid(uuid, 4))
+}
+
+/// Returns a UUID version 7 of RFC 9562, it starts with the current Unix time in milliseconds
+///
+/// Such IDs are sorted by a creation time, so they are good as database keys or file names.
+/// The order of IDs created in the same millisecond is random.
+pub fn uuid_v7() -> io::Result<String> {
+    let mut uuid = [0_u8; 16];
+    fill_random(&mut uuid[6..])?;
+    let millis = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    uuid[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    Ok(format_uuid(uuid, 7))
+}
+
+fn format_uuid(mut uuid: [u8; 16], version: u8) -> String {
+    uuid[6] = (uuid[6] & 0x0f) | version << 4;
+    // the variant of RFC 9562
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    let hex = to_hex(&uuid);
+    format! {"{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]}
+}
+
+/// Calls `getrandom(2)` directly, `None` is returned when it fails before any byte is filled
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn getrandom(buf: &mut [u8]) -> Option<io::Result<()>> {
+    const EINTR: isize = 4;
+    let mut filled = 0;
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let res: isize;
+        // SAFETY: the kernel writes at most `rest.len()` bytes to the valid buffer `rest`
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            std::arch::asm!("syscall", inlateout("rax") 318_isize => res,
+                in("rdi") rest.as_mut_ptr(), in("rsi") rest.len(), in("rdx") 0_usize,
+                lateout("rcx") _, lateout("r11") _, options(nostack));
+            #[cfg(target_arch = "aarch64")]
+            std::arch::asm!("svc 0", in("x8") 278_isize, inlateout("x0") rest.as_mut_ptr() => res,
+                in("x1") rest.len(), in("x2") 0_usize, options(nostack));
+        }
+        match res {
+            n if n > 0 => filled += n as usize,
+            err if err == -EINTR => (),
+            _ if filled == 0 => return None,
+            err => return Some(Err(io::Error::from_raw_os_error(-err as i32))),
+        }
+    }
+    Some(Ok(()))
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn getrandom(_buf: &mut [u8]) -> Option<io::Result<()>> {
+    None
+}
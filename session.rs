@@ -0,0 +1,433 @@
+use crate::{
+    Cookie, SameSite, WebData, fill_random, random_hex, url_comp_decode, url_encode,
+    util::{is_older, remove_older},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+const ID_LEN: usize = 32;
+const DATA_EXT: &str = "session";
+const LOCK_EXT: &str = "lock";
+
+/// Settings of sessions
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// a directory where sessions are stored, it shouldn't be accessible from the web
+    pub dir: PathBuf,
+    pub cookie_name: String,
+    /// a session expires when it wasn't used for this time
+    pub idle_timeout: Duration,
+    /// a session expires after this time regardless of its use
+    pub absolute_timeout: Duration,
+    /// the session cookie is sent only over HTTPS
+    pub secure: bool,
+    /// stale sessions are collected once per this number of started sessions, 0 disables it
+    pub gc_frequency: u8,
+}
+
+impl SessionConfig {
+    /// Creates a config with defaults: 30 min idle and 12 hours absolute timeouts and a secure cookie `sid`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        SessionConfig {
+            dir: dir.into(),
+            cookie_name: "sid".to_string(),
+            idle_timeout: Duration::from_secs(30 * 60),
+            absolute_timeout: Duration::from_secs(12 * 60 * 60),
+            secure: true,
+            gc_frequency: 100,
+        }
+    }
+}
+
+/// A server side session keyed by a session cookie
+///
+/// Data are stored as a file in the configured directory. A session holds an exclusive
+/// lock of its file until it's dropped, so concurrent CGI requests of the same session are serialized.
+///
+/// # Examples
+/// ```
+/// let data = WebData::new();
+/// let mut session = Session::start(&SessionConfig::new("/var/lib/myapp/sessions"), &data)?;
+/// if check_password(&data) {
+///     session.regenerate()?; // prevents a session fixation
+///     session.set("user", "joe");
+/// }
+/// session.save()?;
+/// // add session.cookie_header() to the response headers
+/// ```
+#[derive(Debug)]
+pub struct Session {
+    config: SessionConfig,
+    id: String,
+    data: Vec<(String, String)>,
+    created: u64,
+    accessed: u64,
+    new: bool,
+    destroyed: bool,
+    stale_ids: Vec<String>,
+    /// locks of stale IDs are held until their data are removed
+    stale_locks: Vec<File>,
+    lock: Option<File>,
+}
+
+impl Session {
+    /// Loads a session identified by the request cookie, or creates a new one
+    ///
+    /// An expired or unknown session is replaced by a new empty session with a new ID.
+    pub fn start(config: &SessionConfig, data: &WebData) -> io::Result<Session> {
+        fs::create_dir_all(&config.dir)?;
+        if config.gc_frequency > 0 {
+            // a bias of the modulo of a 32-bit number is negligible for an 8-bit frequency
+            let mut chance = [0_u8; 4];
+            fill_random(&mut chance)?;
+            if u32::from_ne_bytes(chance).is_multiple_of(u32::from(config.gc_frequency)) {
+                let _ = collect_garbage(config);
+            }
+        }
+        let now = now_secs();
+        if let Some(id) = data.cookie(&config.cookie_name)
+            && is_valid_id(&id)
+        {
+            let lock = lock_session(&config.dir, &id)?;
+            match read_session(&file_path(&config.dir, &id, DATA_EXT)) {
+                Ok((created, accessed, data)) if !is_expired(config, created, accessed, now) => {
+                    return Ok(Session {
+                        config: config.clone(),
+                        id,
+                        data,
+                        created,
+                        accessed: now,
+                        new: false,
+                        destroyed: false,
+                        stale_ids: Vec::new(),
+                        stale_locks: Vec::new(),
+                        lock: Some(lock),
+                    });
+                }
+                Ok(_) => remove_session(&config.dir, &id),
+                Err(err) if err.kind() == ErrorKind::NotFound => (),
+                Err(err) => return Err(err),
+            }
+        }
+        let id = new_id()?;
+        Ok(Session {
+            lock: Some(lock_session(&config.dir, &id)?),
+            config: config.clone(),
+            id,
+            data: Vec::new(),
+            created: now,
+            accessed: now,
+            new: true,
+            destroyed: false,
+            stale_ids: Vec::new(),
+            stale_locks: Vec::new(),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Tells that the session was created by the request
+    pub fn is_new(&self) -> bool {
+        self.new
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, val)| val.as_str())
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, val: impl Into<String>) {
+        let key = key.into();
+        let val = val.into();
+        match self.data.iter_mut().find(|(name, _)| *name == key) {
+            Some(entry) => entry.1 = val,
+            None => self.data.push((key, val)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let pos = self.data.iter().position(|(name, _)| name == key)?;
+        Some(self.data.remove(pos).1)
+    }
+
+    /// Returns all stored (key, value) pairs
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.data
+            .iter()
+            .map(|(name, val)| (name.as_str(), val.as_str()))
+    }
+
+    /// Assigns a new ID keeping the data, it has to be called on a login or a privilege change
+    ///
+    /// The absolute timeout starts over. The old session is removed by `save`.
+    pub fn regenerate(&mut self) -> io::Result<()> {
+        let id = new_id()?;
+        let lock = lock_session(&self.config.dir, &id)?;
+        self.stale_ids.push(std::mem::replace(&mut self.id, id));
+        // a concurrent request can't load the old session until it's removed
+        if let Some(old) = self.lock.replace(lock) {
+            self.stale_locks.push(old)
+        }
+        self.created = now_secs();
+        self.new = true;
+        Ok(())
+    }
+
+    /// Removes all data of the session, `cookie_header` will delete the cookie then
+    pub fn destroy(&mut self) {
+        self.data.clear();
+        self.destroyed = true;
+        self.stale_ids.push(self.id.clone())
+    }
+
+    /// Stores the session atomically, a write of a concurrent process can't be seen partially
+    pub fn save(&mut self) -> io::Result<()> {
+        for id in self.stale_ids.drain(..) {
+            remove_session(&self.config.dir, &id)
+        }
+        self.stale_locks.clear();
+        if self.destroyed {
+            return Ok(());
+        }
+        let mut content = format! {"{} {}\n", self.created, self.accessed};
+        for (key, val) in &self.data {
+            content.push_str(&format! {"{}={}\n", url_encode(key), url_encode(val)})
+        }
+        let path = file_path(&self.config.dir, &self.id, DATA_EXT);
+        let mut temp = path.clone();
+        temp.set_extension(format! {"{DATA_EXT}.{}", random_hex(4)?});
+        let res = (|| {
+            let mut file = File::create(&temp)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temp, &path)
+        })();
+        if res.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        res
+    }
+
+    /// Returns the `Set-Cookie` header with the session ID, or deleting the cookie for a destroyed session
+    pub fn cookie_header(&self) -> (String, String) {
+        let cookie = if self.destroyed {
+            Cookie::removal(&self.config.cookie_name)
+        } else {
+            Cookie::new(&self.config.cookie_name, &self.id)
+        };
+        cookie
+            .secure(self.config.secure)
+            .same_site(Some(SameSite::Lax))
+            .header()
+            .unwrap_or_else(|_| {
+                crate::delete_cookie_header(&self.config.cookie_name, Some("/"), None)
+            })
+    }
+}
+
+/// Removes expired sessions of the directory and returns their number
+///
+/// Sessions used by running requests are skipped. Lock files of removed sessions
+/// and temporary files left by interrupted saves are removed too.
+pub fn collect_garbage(config: &SessionConfig) -> io::Result<usize> {
+    remove_older(&config.dir, config.idle_timeout, is_temp_file)?;
+    let now = now_secs();
+    let mut res = 0;
+    for entry in fs::read_dir(&config.dir)?.flatten() {
+        let path = entry.path();
+        let Some(id) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| is_valid_id(stem))
+        else {
+            continue;
+        };
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(DATA_EXT) => (),
+            // a lock without data is left by a session which was never saved
+            Some(LOCK_EXT) if !file_path(&config.dir, id, DATA_EXT).exists() => (),
+            _ => continue,
+        }
+        let Ok(lock) = OpenOptions::new()
+            .write(true)
+            .open(file_path(&config.dir, id, LOCK_EXT))
+        else {
+            continue;
+        };
+        if lock.try_lock().is_err() {
+            continue;
+        }
+        let expired = match read_session(&file_path(&config.dir, id, DATA_EXT)) {
+            Ok((created, accessed, _)) => is_expired(config, created, accessed, now),
+            Err(_) => is_older(&path, config.idle_timeout),
+        };
+        if expired {
+            remove_session(&config.dir, id);
+            // a waiter for the lock notices that it was unlinked, see `lock_session`
+            let _ = fs::remove_file(file_path(&config.dir, id, LOCK_EXT));
+            res += 1
+        }
+    }
+    Ok(res)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn is_expired(config: &SessionConfig, created: u64, accessed: u64, now: u64) -> bool {
+    now.saturating_sub(accessed) > config.idle_timeout.as_secs()
+        || now.saturating_sub(created) > config.absolute_timeout.as_secs()
+}
+
+fn new_id() -> io::Result<String> {
+    random_hex(ID_LEN)
+}
+
+/// an ID is also a file name, so only hex digits are accepted
+fn is_valid_id(id: &str) -> bool {
+    id.len() == ID_LEN * 2 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn file_path(dir: &Path, id: &str, ext: &str) -> PathBuf {
+    dir.join(format! {"{id}.{ext}"})
+}
+
+fn lock_session(dir: &Path, id: &str) -> io::Result<File> {
+    let path = file_path(dir, id, LOCK_EXT);
+    loop {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        lock.lock()?;
+        // the garbage collector could unlink the file while the lock was awaited,
+        // then a newcomer locks a new file, so the lock is taken again
+        if is_linked(&lock, &path) {
+            return Ok(lock);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_linked(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(opened), Ok(linked)) => opened.dev() == linked.dev() && opened.ino() == linked.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_linked(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+/// Removes session data, a lock file is left to the garbage collector,
+/// since another request can wait for it
+fn remove_session(dir: &Path, id: &str) {
+    let _ = fs::remove_file(file_path(dir, id, DATA_EXT));
+}
+
+/// Checks a name `<id>.session.<suffix>` of a file written by `save`
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split_once('.'))
+        .and_then(|(id, rest)| Some((id, rest.strip_prefix(DATA_EXT)?.strip_prefix('.')?)))
+        .is_some_and(|(id, suffix)| is_valid_id(id) && !suffix.is_empty())
+}
+
+type SessionContent = (u64, u64, Vec<(String, String)>);
+
+fn read_session(path: &Path) -> io::Result<SessionContent> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    let mut lines = content.lines();
+    let invalid = || io::Error::new(ErrorKind::InvalidData, "corrupted session");
+    let (created, accessed) = lines
+        .next()
+        .and_then(|line| line.split_once(' '))
+        .ok_or_else(invalid)?;
+    let created = created.parse().map_err(|_| invalid())?;
+    let accessed = accessed.parse().map_err(|_| invalid())?;
+    let mut data = Vec::new();
+    for line in lines {
+        let (key, val) = line.split_once('=').ok_or_else(invalid)?;
+        data.push((
+            url_comp_decode(key).ok_or_else(invalid)?,
+            url_comp_decode(val).ok_or_else(invalid)?,
+        ))
+    }
+    Ok((created, accessed, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config(name: &str) -> SessionConfig {
+        let dir = std::env::temp_dir().join(format! {"simweb-{name}-{}", std::process::id()});
+        let _ = fs::remove_dir_all(&dir);
+        SessionConfig {
+            gc_frequency: 0,
+            ..SessionConfig::new(dir)
+        }
+    }
+
+    fn is_locked(config: &SessionConfig, id: &str) -> bool {
+        let lock = File::open(file_path(&config.dir, id, LOCK_EXT)).unwrap();
+        lock.try_lock().is_err()
+    }
+
+    #[test]
+    fn regenerate_keeps_old_lock() {
+        let config = temp_config("regenerate");
+        let mut session = Session::start(&config, &WebData::new()).unwrap();
+        session.save().unwrap();
+        let old = session.id().to_string();
+        session.regenerate().unwrap();
+        assert!(is_locked(&config, &old));
+        assert!(file_path(&config.dir, &old, DATA_EXT).exists());
+        session.save().unwrap();
+        assert!(!is_locked(&config, &old));
+        assert!(!file_path(&config.dir, &old, DATA_EXT).exists());
+        // the lock file stays for the garbage collector
+        assert!(file_path(&config.dir, &old, LOCK_EXT).exists());
+        drop(session);
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn garbage() {
+        let config = SessionConfig {
+            idle_timeout: Duration::ZERO,
+            ..temp_config("garbage")
+        };
+        let mut session = Session::start(&config, &WebData::new()).unwrap();
+        session.save().unwrap();
+        let id = session.id().to_string();
+        let temp = config.dir.join(format! {"{id}.{DATA_EXT}.0a1b"});
+        fs::write(&temp, "").unwrap();
+        std::thread::sleep(Duration::from_millis(1100));
+        // the session in use is skipped
+        assert_eq!(collect_garbage(&config).unwrap(), 0);
+        assert!(!temp.exists());
+        drop(session);
+        assert_eq!(collect_garbage(&config).unwrap(), 1);
+        assert_eq!(fs::read_dir(&config.dir).unwrap().count(), 0);
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::template;
+use crate::{basic_credentials, csrf_field, template};
 use std::{collections::HashMap, error::Error};
 
 pub trait WebPage {
@@ -44,12 +44,76 @@ pub trait WebPage {
         print! {"Content-type: text/plain\r\n\r\n{err:?}"}
     }
 
+    /// Returns a realm when the page requires HTTP Basic authentication
+    ///
+    /// No authentication is required by default.
+    fn basic_realm(&self) -> Option<&str> {
+        None
+    }
+
+    /// Checks credentials of HTTP Basic authentication, for example using `Htpasswd::verify`
+    ///
+    /// It's called only when `basic_realm` returns a realm.
+    fn authenticate(&self, _user: &str, _password: &str) -> bool {
+        false
+    }
+
+    /// Outs a response requesting an authentication
+    ///
+    /// The method can be implemented for a response customization.
+    fn unauthorized_out(&self, realm: &str) {
+        print! { "Status: {} Unauthorized\r\n", 401 }
+        // a realm can't break the quoted string or the header
+        let realm: String = realm
+            .chars()
+            .filter(|c| !c.is_control() && !matches!(c, '"' | '\\'))
+            .collect();
+        print! { "WWW-Authenticate: Basic realm=\"{realm}\", charset=\"UTF-8\"\r\n" }
+        print! {"Content-type: text/plain\r\n\r\nAuthentication required"}
+    }
+
+    /// Returns a CSRF token of the request, see `session_csrf_token` and `double_submit_token`
+    ///
+    /// When a token is returned, the template variables `csrf_token` and `csrf_field` are set.
+    /// No token by default.
+    fn csrf_token(&self) -> Option<String> {
+        None
+    }
+
+    /// Verifies a CSRF token of the request, generally by calling `WebData::verify_csrf`
+    ///
+    /// A failure is responded by 403 Forbidden. No verification happens by default.
+    fn csrf_verify(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Outs a forbidden response
+    ///
+    /// The method can be implemented for a response customization.
+    fn forbidden_out(&self, err: Box<dyn Error>) {
+        print! { "Status: {} Forbidden\r\n", 403 }
+        print! {"Content-type: text/plain\r\n\r\n{err}"}
+    }
+
     /// The method has an internal implementation
     fn show(&self) {
         // => Result<(), String>
+        if let Some(realm) = self.basic_realm() {
+            match basic_credentials() {
+                Some((user, password)) if self.authenticate(&user, &password) => (),
+                _ => return self.unauthorized_out(realm),
+            }
+        }
+        if let Err(error) = self.csrf_verify() {
+            return self.forbidden_out(error);
+        }
         match self.main_load() {
             Ok(page) => {
                 let mut page_items = HashMap::from([("theme", String::new())]);
+                if let Some(token) = self.csrf_token() {
+                    page_items.insert("csrf_field", csrf_field(&token));
+                    page_items.insert("csrf_token", token);
+                }
                 match self.apply_specific(&mut page_items) {
                     Ok(_) => {
                         if let Some(status) = self.status() {
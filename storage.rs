@@ -0,0 +1,445 @@
+use crate::{
+    Checksums, CollisionPolicy, PartHeaders, Sha256, create_upload_file, random_hex,
+    sanitize_filename, to_hex, util::remove_older,
+};
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+
+/// an extension of files being written
+const PARTIAL_EXT: &str = "~part";
+
+/// locations and contents of files of `MemoryStorage`
+type MemoryFiles = Rc<RefCell<Vec<(PathBuf, Vec<u8>)>>>;
+type SharedCallback = Rc<RefCell<dyn FnMut(UploadEvent) -> io::Result<()>>>;
+
+/// A destination of uploaded files
+///
+/// `WebData::with_storage` stores every file part of a request by the storage. A part is written
+/// to a sink returned by `begin`, then the sink is committed, or aborted when the body can't be read
+/// or doesn't match its digest.
+pub trait UploadStorage: fmt::Debug {
+    /// Begins storing a file of the part
+    fn begin(&mut self, headers: &PartHeaders) -> io::Result<Box<dyn UploadSink>>;
+
+    /// Removes a committed upload which wasn't claimed by the request handler
+    fn remove(&mut self, location: &Path) -> io::Result<()>;
+
+    /// Removes data of interrupted uploads older than the age, the number of removed items is returned
+    ///
+    /// A process killed in the middle of an upload can't abort it, so the storage should be cleaned
+    /// periodically. Nothing is done by default.
+    fn cleanup(&mut self, _max_age: Duration) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    /// Checks if a committed location can be shared by several uploads
+    ///
+    /// A shared file is copied by `UploadedFile::move_to`, so other uploads keep it.
+    fn shares_files(&self) -> bool {
+        false
+    }
+}
+
+/// A writer of one uploaded file
+pub trait UploadSink: Write {
+    /// Makes the written data available, and returns its location
+    ///
+    /// The location is a path of the file for file storages, or a key for others.
+    fn commit(self: Box<Self>, checksums: &Checksums) -> io::Result<PathBuf>;
+
+    /// Discards the written data
+    fn abort(self: Box<Self>);
+}
+
+/// A storage of files in a directory under their sanitized names
+///
+/// A file is written as a `.~part` file, and renamed on a commit, so an incomplete file
+/// is never seen under its name. The directory is created when it's missing,
+/// so it can be per user as `base.join(user)`.
+#[derive(Debug, Clone)]
+pub struct DirStorage {
+    pub dir: PathBuf,
+    pub collision: CollisionPolicy,
+}
+
+impl DirStorage {
+    pub fn new(dir: impl Into<PathBuf>, collision: CollisionPolicy) -> Self {
+        DirStorage {
+            dir: dir.into(),
+            collision,
+        }
+    }
+}
+
+impl UploadStorage for DirStorage {
+    fn begin(&mut self, headers: &PartHeaders) -> io::Result<Box<dyn UploadSink>> {
+        let (file, partial) = create_partial(&self.dir)?;
+        Ok(Box::new(DirSink {
+            file,
+            partial,
+            dir: self.dir.clone(),
+            name: headers.filename.clone().unwrap_or_default(),
+            collision: self.collision,
+        }))
+    }
+
+    fn remove(&mut self, location: &Path) -> io::Result<()> {
+        fs::remove_file(location)
+    }
+
+    fn cleanup(&mut self, max_age: Duration) -> io::Result<usize> {
+        remove_older(&self.dir, max_age, is_partial)
+    }
+}
+
+struct DirSink {
+    file: File,
+    partial: PathBuf,
+    dir: PathBuf,
+    name: String,
+    collision: CollisionPolicy,
+}
+
+impl Write for DirSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl UploadSink for DirSink {
+    fn commit(self: Box<Self>, _checksums: &Checksums) -> io::Result<PathBuf> {
+        let res =
+            create_upload_file(&self.dir, &self.name, self.collision).and_then(|(_, path)| {
+                // the created file holds the name until the rename
+                fs::rename(&self.partial, &path)
+                    .inspect_err(|_| {
+                        let _ = fs::remove_file(&path);
+                    })
+                    .map(|_| path)
+            });
+        if res.is_err() {
+            let _ = fs::remove_file(&self.partial);
+        }
+        res
+    }
+
+    fn abort(self: Box<Self>) {
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+/// A storage naming files by SHA-256 of their content in hex, so equal files are stored once
+///
+/// A client file name isn't used. Since a file can be shared by several uploads,
+/// `remove` keeps it, `UploadedFile::move_to` copies it, and unused files have to be
+/// collected by the application.
+#[derive(Debug, Clone)]
+pub struct ContentAddressedStorage {
+    pub dir: PathBuf,
+}
+
+impl ContentAddressedStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ContentAddressedStorage { dir: dir.into() }
+    }
+}
+
+impl UploadStorage for ContentAddressedStorage {
+    fn begin(&mut self, _headers: &PartHeaders) -> io::Result<Box<dyn UploadSink>> {
+        let (file, partial) = create_partial(&self.dir)?;
+        Ok(Box::new(ContentSink {
+            file,
+            partial,
+            dir: self.dir.clone(),
+            sha: Sha256::new(),
+        }))
+    }
+
+    fn remove(&mut self, _location: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn cleanup(&mut self, max_age: Duration) -> io::Result<usize> {
+        remove_older(&self.dir, max_age, is_partial)
+    }
+
+    fn shares_files(&self) -> bool {
+        true
+    }
+}
+
+struct ContentSink {
+    file: File,
+    partial: PathBuf,
+    dir: PathBuf,
+    /// digests requested by the configuration can omit SHA-256, so it's calculated here
+    sha: Sha256,
+}
+
+impl Write for ContentSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.file.write(buf)?;
+        self.sha.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl UploadSink for ContentSink {
+    fn commit(self: Box<Self>, _checksums: &Checksums) -> io::Result<PathBuf> {
+        let path = self.dir.join(to_hex(&self.sha.finalize()));
+        let res = if path.exists() {
+            fs::remove_file(&self.partial)
+        } else {
+            fs::rename(&self.partial, &path)
+        };
+        if res.is_err() {
+            let _ = fs::remove_file(&self.partial);
+        }
+        res.map(|_| path)
+    }
+
+    fn abort(self: Box<Self>) {
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+/// A storage keeping files in memory, it's useful for tests
+///
+/// Clones share the files, so a clone can be kept to look at files stored through `WebData`.
+///
+/// # Examples
+/// ```
+/// let files = MemoryStorage::default();
+/// let data = WebData::with_storage(&DataConfig::default(), files.clone());
+/// let upload = data.upload("report").ok_or("no report")?;
+/// assert_eq!(files.get(&upload.path).map(|content| content.len() as u64), Some(upload.size));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    files: MemoryFiles,
+    /// a number making the next location unique, it isn't reused after a removal
+    next: Rc<Cell<usize>>,
+}
+
+impl MemoryStorage {
+    /// Returns a content of the stored file
+    pub fn get(&self, location: &Path) -> Option<Vec<u8>> {
+        self.files
+            .borrow()
+            .iter()
+            .find(|(key, _)| key == location)
+            .map(|(_, content)| content.clone())
+    }
+
+    /// Returns locations of all stored files in the order they were committed
+    pub fn locations(&self) -> Vec<PathBuf> {
+        self.files
+            .borrow()
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+impl UploadStorage for MemoryStorage {
+    fn begin(&mut self, headers: &PartHeaders) -> io::Result<Box<dyn UploadSink>> {
+        Ok(Box::new(MemorySink {
+            files: self.files.clone(),
+            next: self.next.clone(),
+            name: sanitize_filename(headers.filename.as_deref().unwrap_or_default()),
+            content: Vec::new(),
+        }))
+    }
+
+    fn remove(&mut self, location: &Path) -> io::Result<()> {
+        self.files.borrow_mut().retain(|(key, _)| key != location);
+        Ok(())
+    }
+}
+
+struct MemorySink {
+    files: MemoryFiles,
+    next: Rc<Cell<usize>>,
+    name: String,
+    content: Vec<u8>,
+}
+
+impl Write for MemorySink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.content.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl UploadSink for MemorySink {
+    fn commit(self: Box<Self>, _checksums: &Checksums) -> io::Result<PathBuf> {
+        // a number makes keys of equal names unique
+        let key = PathBuf::from(format! {"{}/{}", self.next.get(), self.name});
+        self.next.set(self.next.get() + 1);
+        self.files.borrow_mut().push((key.clone(), self.content));
+        Ok(key)
+    }
+
+    fn abort(self: Box<Self>) {}
+}
+
+/// An event of an upload reported to `CallbackStorage`
+#[derive(Debug)]
+pub enum UploadEvent<'a> {
+    Begin(&'a PartHeaders),
+    Data(&'a [u8]),
+    Commit(&'a Checksums),
+    Abort,
+    /// an upload committed earlier wasn't claimed
+    Remove(&'a Path),
+}
+
+/// A storage passing uploaded data to a callback, for example to stream it to another service
+///
+/// The location of a committed upload is the sanitized client file name. Only one upload
+/// is in progress at a time, so events of an upload go from `Begin` to `Commit` or `Abort`.
+///
+/// # Examples
+/// ```
+/// let storage = CallbackStorage::new(move |event| match event {
+///     UploadEvent::Begin(headers) => bucket.start(headers.filename.as_deref()),
+///     UploadEvent::Data(chunk) => bucket.append(chunk),
+///     UploadEvent::Commit(_) => bucket.finish(),
+///     UploadEvent::Abort | UploadEvent::Remove(_) => bucket.discard(),
+/// });
+/// let data = WebData::with_storage(&DataConfig::default(), storage);
+/// ```
+pub struct CallbackStorage {
+    callback: SharedCallback,
+}
+
+impl CallbackStorage {
+    pub fn new(callback: impl FnMut(UploadEvent) -> io::Result<()> + 'static) -> Self {
+        CallbackStorage {
+            callback: Rc::new(RefCell::new(callback)),
+        }
+    }
+}
+
+impl fmt::Debug for CallbackStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallbackStorage").finish_non_exhaustive()
+    }
+}
+
+impl UploadStorage for CallbackStorage {
+    fn begin(&mut self, headers: &PartHeaders) -> io::Result<Box<dyn UploadSink>> {
+        (self.callback.borrow_mut())(UploadEvent::Begin(headers))?;
+        Ok(Box::new(CallbackSink {
+            callback: self.callback.clone(),
+            name: sanitize_filename(headers.filename.as_deref().unwrap_or_default()),
+        }))
+    }
+
+    fn remove(&mut self, location: &Path) -> io::Result<()> {
+        (self.callback.borrow_mut())(UploadEvent::Remove(location))
+    }
+}
+
+struct CallbackSink {
+    callback: SharedCallback,
+    name: String,
+}
+
+impl Write for CallbackSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.callback.borrow_mut())(UploadEvent::Data(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl UploadSink for CallbackSink {
+    fn commit(self: Box<Self>, checksums: &Checksums) -> io::Result<PathBuf> {
+        (self.callback.borrow_mut())(UploadEvent::Commit(checksums))?;
+        Ok(PathBuf::from(self.name))
+    }
+
+    fn abort(self: Box<Self>) {
+        let _ = (self.callback.borrow_mut())(UploadEvent::Abort);
+    }
+}
+
+/// Creates a file with a random name and the partial extension in the directory
+fn create_partial(dir: &Path) -> io::Result<(File, PathBuf)> {
+    fs::create_dir_all(dir)?;
+    loop {
+        let path = dir.join(format! {"{}.{PARTIAL_EXT}", random_hex(16)?});
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn is_partial(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == PARTIAL_EXT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_locations() {
+        let mut storage = MemoryStorage::default();
+        let headers = PartHeaders {
+            filename: Some("a.txt".to_string()),
+            ..Default::default()
+        };
+        let store = |storage: &mut MemoryStorage| {
+            let sink = storage.begin(&headers).unwrap();
+            sink.commit(&Checksums::default()).unwrap()
+        };
+        let first = store(&mut storage);
+        let second = store(&mut storage);
+        storage.remove(&first).unwrap();
+        let third = store(&mut storage);
+        assert_ne!(second, third);
+        assert_eq!(storage.locations(), [second, third]);
+    }
+
+    #[test]
+    fn dir_commit_failure() {
+        let dir = std::env::temp_dir().join(random_hex(8).unwrap());
+        let mut storage = DirStorage::new(&dir, CollisionPolicy::Reject);
+        let headers = PartHeaders {
+            filename: Some("a.txt".to_string()),
+            ..Default::default()
+        };
+        let sink = storage.begin(&headers).unwrap();
+        // the rename fails when the written file is gone
+        let partial = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        fs::remove_file(partial).unwrap();
+        assert!(sink.commit(&Checksums::default()).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
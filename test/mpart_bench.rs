@@ -0,0 +1,78 @@
+// Measures throughput of the multipart parsing on a large upload
+// usage: mpart_bench [size in MB]
+extern crate simweb;
+use std::{env, io, time::Instant};
+
+use simweb::{MPart, MultipartReader, Storage};
+
+const BOUNDARY: &str = "----WebKitFormBoundary7MA4YWxkTrZu0gW";
+const FILE_SIZE: usize = 1024 * 1024;
+
+fn main() -> io::Result<()> {
+    let size_mb: usize = env::args()
+        .nth(1)
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(128);
+    let body = make_body(size_mb * 1024 * 1024);
+
+    let started = Instant::now();
+    let mut multipart = MultipartReader::new(&body[..], BOUNDARY.as_bytes());
+    let mut total = 0;
+    while let Some(mut part) = multipart.next_part()? {
+        total += io::copy(&mut part, &mut io::sink())?
+    }
+    report("MultipartReader", total as usize, started);
+
+    let started = Instant::now();
+    let mut reader = &body[..];
+    let mut parts = MPart::from(&mut reader, BOUNDARY.as_bytes());
+    let total = parts
+        .by_ref()
+        .map(|part| match part.content {
+            Storage::Mem(content) => content.len(),
+            _ => 0,
+        })
+        .sum();
+    if let Some(err) = parts.error() {
+        return Err(io::Error::new(err.kind(), err.to_string()));
+    }
+    report("MPart", total, started);
+    Ok(())
+}
+
+/// a text field and files of pseudo random bytes with line breaks and dashes, like binary uploads
+///
+/// Every file is 1 MB, so `MPart` keeps them in memory.
+fn make_body(size: usize) -> Vec<u8> {
+    let mut res =
+        format! {"--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nbench\r\n"}
+            .into_bytes();
+    let mut seed = 0x2545f4914f6cdd1d_u64;
+    for i in 0..size.div_ceil(FILE_SIZE) {
+        res.extend_from_slice(
+            format! {"--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; \
+            filename=\"data{i}.bin\"\r\nContent-Type: application/octet-stream\r\n\r\n"}
+            .as_bytes(),
+        );
+        res.extend((0..FILE_SIZE).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            match seed % 64 {
+                0 => b'\r',
+                1 => b'\n',
+                2 => b'-',
+                _ => seed as u8,
+            }
+        }));
+        res.extend_from_slice(b"\r\n")
+    }
+    res.extend_from_slice(format! {"--{BOUNDARY}--\r\n"}.as_bytes());
+    res
+}
+
+fn report(name: &str, total: usize, started: Instant) {
+    let elapsed = started.elapsed().as_secs_f64();
+    println! {"{name}: {} MB in {elapsed:.3} s, {:.0} MB/s", total / (1024 * 1024),
+    total as f64 / (1024.0 * 1024.0) / elapsed}
+}
@@ -0,0 +1,523 @@
+use crate::{ChecksumReader, Checksums, DataConfig, PartHeaders, UploadStorage, random_hex};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// a limit of a file name length in bytes of most file systems
+const MAX_NAME_LEN: usize = 255;
+/// a name used when nothing is left of a client file name
+const DEFAULT_NAME: &str = "upload";
+const MAX_SUFFIX: u32 = 10_000;
+/// a number of first bytes of a file used to detect its type
+const SNIFF_LEN: usize = 512;
+const COPY_BUF_SIZE: usize = 64 * 1024;
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// What to do when an uploaded file has a name of an existing file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CollisionPolicy {
+    /// a number is added to the name, like `report-1.pdf`
+    #[default]
+    UniqueSuffix,
+    /// a random hex name keeping the extension is used
+    Random,
+    /// the upload fails with `ErrorKind::AlreadyExists`
+    Reject,
+    /// the existing file is replaced
+    Overwrite,
+}
+
+/// A file uploaded by `multipart/form-data`
+///
+/// When `DataConfig::remove_unclaimed` is set, the stored file is removed when `WebData` is dropped
+/// unless it was claimed by `keep`, `move_to` or `persist_in`. Moving and opening the file
+/// work only for storages keeping files in a file system.
+///
+/// # Examples
+/// ```
+/// let mut data = WebData::with_config(&DataConfig { remove_unclaimed: true, ..Default::default() });
+/// for upload in data.uploads_mut("photo") {
+///     if upload.sniffed_type.is_some_and(|mime| mime.starts_with("image/")) {
+///         upload.persist_in(Path::new("/var/www/photos"), CollisionPolicy::UniqueSuffix)?;
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct UploadedFile {
+    /// a name of the form field
+    pub field: String,
+    /// a file name as sent by the client
+    pub original_name: String,
+    /// a current path of the stored file, its name is sanitized,
+    /// or a location given by the `UploadStorage`
+    pub path: PathBuf,
+    /// a size in bytes
+    pub size: u64,
+    /// a type declared by the client in the part header
+    pub content_type: Option<String>,
+    /// a type detected by the first bytes of the content, `None` for an empty file
+    pub sniffed_type: Option<&'static str>,
+    /// digests requested by `DataConfig::checksums` or given by headers of the part
+    pub checksums: Checksums,
+    /// all headers of the part, names are in lowercase
+    pub headers: Vec<(String, String)>,
+    unclaimed: bool,
+    /// the file can belong to other uploads too
+    shared: bool,
+}
+
+impl UploadedFile {
+    /// Stores a part body by the storage, the stored data is aborted on an error
+    ///
+    /// A content not matching a digest header of the part is an error of `ErrorKind::InvalidData`.
+    pub(crate) fn store(
+        headers: &PartHeaders,
+        body: &mut impl Read,
+        storage: &mut dyn UploadStorage,
+        config: &DataConfig,
+    ) -> io::Result<Self> {
+        let mut sink = storage.begin(headers)?;
+        let mut body = ChecksumReader::new(body, &config.checksums, headers);
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        let mut buf = vec![0; COPY_BUF_SIZE];
+        let mut size = 0;
+        loop {
+            let len = match body.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    sink.abort();
+                    return Err(err);
+                }
+            };
+            let chunk = &buf[..len];
+            if head.len() < SNIFF_LEN {
+                head.extend_from_slice(&chunk[..len.min(SNIFF_LEN - head.len())])
+            }
+            if let Err(err) = sink.write_all(chunk) {
+                sink.abort();
+                return Err(err);
+            }
+            size += len as u64
+        }
+        let checksums = body.finish();
+        if let Err(err) = checksums.verify(headers) {
+            sink.abort();
+            return Err(io::Error::new(ErrorKind::InvalidData, err.reason));
+        }
+        Ok(UploadedFile {
+            field: headers.name.clone(),
+            original_name: headers.filename.clone().unwrap_or_default(),
+            path: sink.commit(&checksums)?,
+            size,
+            content_type: headers.content_type.clone(),
+            sniffed_type: sniff_content_type(&head),
+            checksums,
+            headers: headers.headers.clone(),
+            unclaimed: config.remove_unclaimed,
+            shared: storage.shares_files(),
+        })
+    }
+
+    /// Claims the file, so it stays where it is
+    pub fn keep(&mut self) {
+        self.unclaimed = false
+    }
+
+    /// Moves the file to the path and claims it
+    ///
+    /// The file is copied when the path is on another file system, or when the storage shares it
+    /// with other uploads. An existing file is replaced. A parameter of the field keeps the former path.
+    pub fn move_to(&mut self, dest: impl AsRef<Path>) -> io::Result<()> {
+        let dest = dest.as_ref();
+        if self.shared {
+            fs::copy(&self.path, dest)?;
+        } else {
+            match fs::rename(&self.path, dest) {
+                Ok(()) => (),
+                Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+                    fs::copy(&self.path, dest)?;
+                    fs::remove_file(&self.path)?
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        self.path = dest.to_path_buf();
+        self.unclaimed = false;
+        Ok(())
+    }
+
+    /// Moves the file to the directory under its sanitized original name and claims it
+    ///
+    /// A taken name is resolved by the policy. The new path is returned.
+    pub fn persist_in(&mut self, dir: &Path, policy: CollisionPolicy) -> io::Result<&Path> {
+        let (_, dest) = create_upload_file(dir, &self.original_name, policy)?;
+        if let Err(err) = self.move_to(&dest) {
+            let _ = fs::remove_file(&dest);
+            return Err(err);
+        }
+        Ok(&self.path)
+    }
+
+    /// Opens the stored file for reading
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Checks if the file has to be removed with `WebData`
+    pub(crate) fn is_unclaimed(&self) -> bool {
+        self.unclaimed
+    }
+}
+
+/// Detects a type of content by its first bytes
+///
+/// Common image, audio, video, document and archive signatures are recognized.
+/// Other content is `text/plain` when it's UTF-8 without control characters,
+/// or `application/octet-stream`. `None` is returned for empty content.
+///
+/// # Examples
+/// ```
+/// assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("image/png"));
+/// assert_eq!(sniff_content_type(b"%PDF-1.7"), Some("application/pdf"));
+/// assert_eq!(sniff_content_type("Grüße\n".as_bytes()), Some("text/plain"));
+/// ```
+pub fn sniff_content_type(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"II*\0", "image/tiff"),
+        (b"MM\0*", "image/tiff"),
+        (b"\0\0\x01\0", "image/x-icon"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"\0asm", "application/wasm"),
+        (b"OggS", "application/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"fLaC", "audio/flac"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+    if head.is_empty() {
+        return None;
+    }
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(sign, _)| head.starts_with(sign)) {
+        return Some(mime);
+    }
+    // a text can begin with `BM` too, so the offset and the header size are checked
+    if let Some(
+        [
+            b'B',
+            b'M',
+            _,
+            _,
+            _,
+            _,
+            0,
+            0,
+            0,
+            0,
+            o0,
+            o1,
+            o2,
+            o3,
+            h0,
+            h1,
+            h2,
+            h3,
+        ],
+    ) = head.get(..18)
+    {
+        let offset = u32::from_le_bytes([*o0, *o1, *o2, *o3]);
+        let header = u32::from_le_bytes([*h0, *h1, *h2, *h3]);
+        if matches!(header, 12 | 40 | 52 | 56 | 64 | 108 | 124) && offset >= 14 + header {
+            return Some("image/bmp");
+        }
+    }
+    match head.get(..12) {
+        Some([b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P']) => {
+            return Some("image/webp");
+        }
+        Some([b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E']) => {
+            return Some("audio/wav");
+        }
+        Some([_, _, _, _, b'f', b't', b'y', b'p', ..]) => return Some("video/mp4"),
+        _ => (),
+    }
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // the head can end in the middle of a character
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&head[..err.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return Some("application/octet-stream"),
+    };
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+    {
+        return Some("application/octet-stream");
+    }
+    let start = text.trim_start();
+    let start = start.get(..14).unwrap_or(start).to_ascii_lowercase();
+    Some(
+        if start.starts_with("<!doctype html") || start.starts_with("<html") {
+            "text/html"
+        } else if start.starts_with("<svg") {
+            "image/svg+xml"
+        } else if start.starts_with("<?xml") {
+            "application/xml"
+        } else {
+            "text/plain"
+        },
+    )
+}
+
+/// Makes a client file name safe to use as a name of a file in an upload directory
+///
+/// Directories of Unix and Windows paths are removed. Control and Windows reserved characters,
+/// and bidirectional overrides are replaced by `_`. Leading dots are removed, so the name can't be
+/// hidden, `.` or `..`. Windows device names get a `_` prefix, and the length is limited
+/// to 255 bytes keeping the extension. `upload` is returned when nothing is left.
+///
+/// # Examples
+/// ```
+/// assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+/// assert_eq!(sanitize_filename(r"C:\Users\joe\report?.pdf"), "report_.pdf");
+/// assert_eq!(sanitize_filename("nul.txt"), "_nul.txt");
+/// assert_eq!(sanitize_filename(".htaccess"), "htaccess");
+/// assert_eq!(sanitize_filename(".."), "upload");
+/// ```
+pub fn sanitize_filename(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            // bidirectional overrides can disguise an extension
+            '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows ignores trailing dots and spaces
+    let name = name
+        .trim_start_matches(['.', ' '])
+        .trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        return DEFAULT_NAME.to_string();
+    }
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    let mut res = if WINDOWS_RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        format! {"_{name}"}
+    } else {
+        name.to_string()
+    };
+    if res.len() > MAX_NAME_LEN {
+        let ext = extension(&res)
+            .filter(|ext| ext.len() < 32)
+            .unwrap_or_default();
+        let mut stem_len = MAX_NAME_LEN - ext.len();
+        while !res.is_char_boundary(stem_len) {
+            stem_len -= 1
+        }
+        res = format! {"{}{ext}", &res[..stem_len]}
+    }
+    res
+}
+
+/// Creates a file for an upload in the directory, the name is sanitized first
+///
+/// Except `Overwrite`, the file is created only when it doesn't exist, so concurrent uploads
+/// of the same name can't share a file. The created file and its path are returned.
+/// A file written elsewhere can be renamed to the path, since the name is already taken by it.
+pub fn create_upload_file(
+    dir: &Path,
+    filename: &str,
+    policy: CollisionPolicy,
+) -> io::Result<(File, PathBuf)> {
+    let name = sanitize_filename(filename);
+    let create = |name: &str| {
+        let path = dir.join(name);
+        let file = if policy == CollisionPolicy::Overwrite {
+            File::create(&path)?
+        } else {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)?
+        };
+        Ok((file, path))
+    };
+    match policy {
+        CollisionPolicy::Overwrite | CollisionPolicy::Reject => create(&name),
+        CollisionPolicy::Random => loop {
+            let ext = extension(&name).unwrap_or_default();
+            match create(&format! {"{}{ext}", random_hex(16)?}) {
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                res => return res,
+            }
+        },
+        CollisionPolicy::UniqueSuffix => {
+            let (stem, ext) = match extension(&name) {
+                Some(ext) => (&name[..name.len() - ext.len()], ext),
+                None => (name.as_str(), ""),
+            };
+            for i in 0..MAX_SUFFIX {
+                let candidate = if i == 0 {
+                    name.clone()
+                } else {
+                    let suffix = format! {"-{i}"};
+                    // a name at the limit gives room for the suffix
+                    let mut stem_len = stem
+                        .len()
+                        .min(MAX_NAME_LEN.saturating_sub(suffix.len() + ext.len()));
+                    while !stem.is_char_boundary(stem_len) {
+                        stem_len -= 1
+                    }
+                    format! {"{}{suffix}{ext}", &stem[..stem_len]}
+                };
+                match create(&candidate) {
+                    Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                    res => return res,
+                }
+            }
+            Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format! {"no free name for {name}"},
+            ))
+        }
+    }
+}
+
+/// Returns an extension with the dot, like `.gz` of `data.tar.gz`
+fn extension(name: &str) -> Option<&str> {
+    name.rfind('.')
+        .filter(|&pos| pos > 0)
+        .map(|pos| &name[pos..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(random_hex(8).unwrap())
+    }
+
+    fn headers(name: &str) -> PartHeaders {
+        PartHeaders {
+            name: "f".to_string(),
+            filename: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn store(storage: &mut dyn UploadStorage, name: &str, content: &[u8]) -> UploadedFile {
+        let config = DataConfig {
+            remove_unclaimed: true,
+            ..Default::default()
+        };
+        UploadedFile::store(&headers(name), &mut &content[..], storage, &config).unwrap()
+    }
+
+    #[test]
+    fn sniff_bmp() {
+        let bmp = b"BM\x46\0\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0\x01\0\0\0";
+        assert_eq!(sniff_content_type(bmp), Some("image/bmp"));
+        assert_eq!(
+            sniff_content_type(b"BMI,weight\n22.5,70\n"),
+            Some("text/plain")
+        );
+        assert_eq!(sniff_content_type(b"BMW 320i"), Some("text/plain"));
+    }
+
+    #[test]
+    fn store_in_memory() {
+        let mut files = crate::MemoryStorage::default();
+        let upload = store(&mut files, "../a.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+        assert_eq!(upload.original_name, "../a.png");
+        assert_eq!(upload.size, 16);
+        assert_eq!(upload.sniffed_type, Some("image/png"));
+        assert!(upload.is_unclaimed());
+        assert_eq!(files.get(&upload.path).map(|file| file.len()), Some(16));
+        let mut headers = headers("b.txt");
+        headers.headers.push((
+            "content-md5".to_string(),
+            "DMF1ucDxtqgxw5niaXcmYQ==".to_string(),
+        ));
+        let err = UploadedFile::store(&headers, &mut &b"b"[..], &mut files, &DataConfig::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(files.locations(), [upload.path]);
+    }
+
+    #[test]
+    fn persist_policies() {
+        let dir = temp_dir();
+        let out = dir.join("out");
+        let mut storage = crate::DirStorage::new(dir.join("in"), CollisionPolicy::UniqueSuffix);
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("a.txt"), "old").unwrap();
+
+        let mut upload = store(&mut storage, "a.txt", b"new");
+        let stored = upload.path.clone();
+        let err = upload
+            .persist_in(&out, CollisionPolicy::Reject)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert!(stored.exists());
+        assert!(upload.is_unclaimed());
+        assert_eq!(
+            upload
+                .persist_in(&out, CollisionPolicy::UniqueSuffix)
+                .unwrap(),
+            out.join("a-1.txt")
+        );
+        assert!(!stored.exists());
+        assert!(!upload.is_unclaimed());
+
+        let mut upload = store(&mut storage, "a.txt", b"new");
+        let path = upload.persist_in(&out, CollisionPolicy::Random).unwrap();
+        assert_eq!(path.parent(), Some(out.as_path()));
+        assert!(path.to_string_lossy().ends_with(".txt"));
+        assert_ne!(path.file_name().unwrap(), "a.txt");
+
+        let mut upload = store(&mut storage, "a.txt", b"new");
+        upload.persist_in(&out, CollisionPolicy::Overwrite).unwrap();
+        assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"new");
+
+        let mut upload = store(&mut storage, "a.txt", b"moved");
+        upload.move_to(dir.join("b.txt")).unwrap();
+        assert_eq!(fs::read(dir.join("b.txt")).unwrap(), b"moved");
+        assert_eq!(fs::read_dir(dir.join("in")).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn long_name_suffix() {
+        let dir = temp_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let name = format! {"{}.txt", "é".repeat(200)};
+        let (_, first) = create_upload_file(&dir, &name, CollisionPolicy::UniqueSuffix).unwrap();
+        let (_, second) = create_upload_file(&dir, &name, CollisionPolicy::UniqueSuffix).unwrap();
+        let second = second.file_name().unwrap().to_str().unwrap();
+        assert_ne!(first.file_name().unwrap(), second);
+        assert!(second.len() <= MAX_NAME_LEN);
+        assert!(second.ends_with("-1.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,7 @@
 use std::fs;
+use std::io;
 use std::path::Path;
+use std::time::Duration;
 
 /// lists all files with specified extensions recusively from
 /// the given directory
@@ -37,3 +39,31 @@ pub fn list_files(path: impl AsRef<Path>, ext: &impl AsRef<str>) -> Vec<String>
     }
     res
 }
+
+/// Checks if the file was modified longer than the age ago
+pub(crate) fn is_older(path: &Path, age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.elapsed().ok())
+        .is_some_and(|elapsed| elapsed > age)
+}
+
+/// Removes files of the directory accepted by the filter and older than the age,
+/// the number of removed files is returned
+///
+/// A file removed by someone else meanwhile is skipped.
+pub(crate) fn remove_older(
+    dir: &Path,
+    max_age: Duration,
+    filter: impl Fn(&Path) -> bool,
+) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if filter(&path) && is_older(&path, max_age) && fs::remove_file(&path).is_ok() {
+            removed += 1
+        }
+    }
+    Ok(removed)
+}
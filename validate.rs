@@ -0,0 +1,551 @@
+use crate::{WebData, html_encode};
+use std::{collections::HashMap, error::Error, fmt};
+
+/// Describes why a parameter was rejected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldErrorKind {
+    Missing,
+    Parse,
+    Multiple,
+    Length,
+    Range,
+    Pattern,
+    Email,
+    Url,
+    OneOf,
+}
+
+/// The structure holds a failure of one parameter
+#[derive(Debug, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub kind: FieldErrorKind,
+    pub message: String,
+    var: String,
+}
+
+impl FieldError {
+    /// Creates a field error with the given message
+    pub fn new(field: impl Into<String>, kind: FieldErrorKind, message: impl Into<String>) -> Self {
+        let field = field.into();
+        FieldError {
+            var: format! {"{field}_error"},
+            field,
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Returns a template variable name used for the error, as `age_error` for the field `age`
+    pub fn var_name(&self) -> &str {
+        &self.var
+    }
+}
+
+impl Error for FieldError {}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// A list of all failures found during a validation
+#[derive(Debug, Clone, Default)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn push(&mut self, err: FieldError) {
+        self.errors.push(err)
+    }
+
+    /// Returns all errors of the given field
+    pub fn field(&self, name: &str) -> impl Iterator<Item = &FieldError> {
+        self.errors.iter().filter(move |err| err.field == name)
+    }
+
+    /// Converts the list in `Err` when it isn't empty
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+
+    /// Fills a map used by `interpolate` for a form redisplay
+    ///
+    /// Every failed field gets a variable `<field>_error` with HTML encoded messages,
+    /// and the variable `errors` gets all messages as a HTML list.
+    pub fn fill<'a>(&'a self, page_map: &mut HashMap<&'a str, String>) {
+        let mut all = String::new();
+        for err in &self.errors {
+            let message = html_encode(&err.message);
+            page_map
+                .entry(err.var_name())
+                .and_modify(|val| {
+                    val.push_str("<br>");
+                    val.push_str(&message)
+                })
+                .or_insert_with(|| message.to_string());
+            all.push_str(&format! {"<li>{}</li>", html_encode(&err.to_string())})
+        }
+        if !all.is_empty() {
+            page_map.insert("errors", format! {"<ul>{all}</ul>"});
+        }
+    }
+}
+
+impl Error for ValidationErrors {}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, err) in self.errors.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?
+            }
+            write!(f, "{err}")?
+        }
+        Ok(())
+    }
+}
+
+/// A validation rule applied to a parameter value
+///
+/// Values are trimmed before checking. Rules except `Required` are skipped when
+/// a parameter is absent or empty.
+#[derive(Debug, Clone, Copy)]
+pub enum Rule<'a> {
+    Required,
+    /// minimal and maximal length in chars
+    Length(usize, usize),
+    /// minimal and maximal numeric value
+    Range(f64, f64),
+    /// a whole value has to match the pattern, see `pattern_match`
+    Pattern(&'a str),
+    Email,
+    Url,
+    OneOf(&'a [&'a str]),
+}
+
+/// Validates parameters of `WebData` accordingly to a declared set of rules
+///
+/// # Examples
+/// ```
+/// let errors = Validator::new()
+///     .field("age", &[Rule::Required, Rule::Range(18.0, 120.0)])
+///     .field("email", &[Rule::Email])
+///     .field("color", &[Rule::OneOf(&["red", "green"])])
+///     .validate(&WebData::new());
+/// ```
+#[derive(Default)]
+pub struct Validator<'a> {
+    fields: Vec<(&'a str, &'a [Rule<'a>])>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new() -> Self {
+        Validator { fields: Vec::new() }
+    }
+
+    /// Adds rules for a parameter with the given name
+    pub fn field(mut self, name: &'a str, rules: &'a [Rule<'a>]) -> Self {
+        self.fields.push((name, rules));
+        self
+    }
+
+    /// Checks all fields and collects every failure
+    pub fn validate(&self, data: &WebData) -> Result<(), ValidationErrors> {
+        let mut res = ValidationErrors::default();
+        for (name, rules) in &self.fields {
+            let values = data.params(name).unwrap_or_default();
+            let values: Vec<_> = values
+                .iter()
+                .map(|val| val.trim())
+                .filter(|val| !val.is_empty())
+                .collect();
+            if values.is_empty() {
+                if rules.iter().any(|rule| matches!(rule, Rule::Required)) {
+                    res.push(FieldError::new(
+                        *name,
+                        FieldErrorKind::Missing,
+                        "a value is required",
+                    ))
+                }
+                continue;
+            }
+            for value in values {
+                for rule in rules.iter() {
+                    if let Err(err) = check_rule(name, value, rule) {
+                        res.push(err)
+                    }
+                }
+            }
+        }
+        res.into_result()
+    }
+}
+
+fn check_rule(name: &str, value: &str, rule: &Rule) -> Result<(), FieldError> {
+    match *rule {
+        Rule::Required => Ok(()),
+        Rule::Length(min, max) => {
+            let len = value.chars().count();
+            if len < min || len > max {
+                Err(FieldError::new(
+                    name,
+                    FieldErrorKind::Length,
+                    format! {"the length has to be from {min} to {max}"},
+                ))
+            } else {
+                Ok(())
+            }
+        }
+        Rule::Range(min, max) => match value.parse::<f64>() {
+            Ok(num) if num >= min && num <= max => Ok(()),
+            Ok(_) => Err(FieldError::new(
+                name,
+                FieldErrorKind::Range,
+                format! {"the value has to be from {min} to {max}"},
+            )),
+            Err(_) => Err(FieldError::new(
+                name,
+                FieldErrorKind::Parse,
+                "the value isn't a number",
+            )),
+        },
+        Rule::Pattern(pat) => {
+            if pattern_match(pat, value) {
+                Ok(())
+            } else {
+                Err(FieldError::new(
+                    name,
+                    FieldErrorKind::Pattern,
+                    "the value has an invalid format",
+                ))
+            }
+        }
+        Rule::Email => {
+            if is_email(value) {
+                Ok(())
+            } else {
+                Err(FieldError::new(
+                    name,
+                    FieldErrorKind::Email,
+                    "the value isn't an email",
+                ))
+            }
+        }
+        Rule::Url => {
+            if is_url(value) {
+                Ok(())
+            } else {
+                Err(FieldError::new(
+                    name,
+                    FieldErrorKind::Url,
+                    "the value isn't an URL",
+                ))
+            }
+        }
+        Rule::OneOf(set) => {
+            if set.contains(&value) {
+                Ok(())
+            } else {
+                Err(FieldError::new(
+                    name,
+                    FieldErrorKind::OneOf,
+                    format! {"the value has to be one of: {}", set.join(", ")},
+                ))
+            }
+        }
+    }
+}
+
+fn is_domain(domain: &str) -> bool {
+    domain.len() < 254
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() < 64
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+}
+
+/// Checks that a string has a shape of an email address as `name@domain.tld`
+pub fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.rsplit_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && local.len() < 65
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c))
+        && domain.contains('.')
+        && is_domain(domain)
+}
+
+/// Checks that a string has a shape of an absolute http(s) URL
+pub fn is_url(value: &str) -> bool {
+    let Some((scheme, rest)) = value.split_once("://") else {
+        return false;
+    };
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return false;
+    }
+    if value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !host.ends_with(']') => {
+            if port.is_empty() || !port.chars().all(|c| c.is_ascii_digit()) {
+                return false;
+            }
+            name
+        }
+        _ => host,
+    };
+    if let Some(ip6) = host.strip_prefix('[') {
+        ip6.strip_suffix(']').is_some_and(|ip6| {
+            !ip6.is_empty() && ip6.chars().all(|c| c.is_ascii_hexdigit() || c == ':')
+        })
+    } else {
+        !host.is_empty() && is_domain(host)
+    }
+}
+
+#[derive(Debug)]
+enum Atom {
+    Any,
+    Char(char),
+    Class(Vec<(char, char)>, bool),
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Any => true,
+            Atom::Char(ch) => *ch == c,
+            Atom::Class(ranges, negated) => {
+                ranges.iter().any(|(from, to)| c >= *from && c <= *to) != *negated
+            }
+        }
+    }
+}
+
+fn escape_class(c: char) -> Atom {
+    match c {
+        'd' => Atom::Class(vec![('0', '9')], false),
+        'D' => Atom::Class(vec![('0', '9')], true),
+        'w' => Atom::Class(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], false),
+        'W' => Atom::Class(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], true),
+        's' => Atom::Class(vec![(' ', ' '), ('\t', '\r')], false),
+        'S' => Atom::Class(vec![(' ', ' '), ('\t', '\r')], true),
+        _ => Atom::Char(c),
+    }
+}
+
+fn compile_pattern(pat: &str) -> Option<Vec<(Atom, usize, usize)>> {
+    let mut res = Vec::new();
+    let mut chars = pat.chars().peekable();
+    while let Some(c) = chars.next() {
+        let atom = match c {
+            '.' => Atom::Any,
+            '\\' => escape_class(chars.next()?),
+            '[' => {
+                let mut ranges = Vec::new();
+                let negated = chars.next_if_eq(&'^').is_some();
+                let mut first = true;
+                loop {
+                    let c = chars.next()?;
+                    let from = match c {
+                        ']' if !first => break,
+                        '\\' => match escape_class(chars.next()?) {
+                            Atom::Class(class, false) => {
+                                ranges.extend(class);
+                                first = false;
+                                continue;
+                            }
+                            Atom::Char(c) => c,
+                            _ => return None,
+                        },
+                        _ => c,
+                    };
+                    first = false;
+                    if chars.peek() == Some(&'-') {
+                        chars.next();
+                        match chars.next()? {
+                            ']' => {
+                                ranges.push((from, from));
+                                ranges.push(('-', '-'));
+                                break;
+                            }
+                            to => ranges.push((from, to)),
+                        }
+                    } else {
+                        ranges.push((from, from))
+                    }
+                }
+                Atom::Class(ranges, negated)
+            }
+            _ => Atom::Char(c),
+        };
+        let (min, max) = match chars.peek() {
+            Some('*') => (0, usize::MAX),
+            Some('+') => (1, usize::MAX),
+            Some('?') => (0, 1),
+            Some('{') => {
+                chars.next();
+                let mut spec = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => spec.push(c),
+                    }
+                }
+                let (min, max) = match spec.split_once(',') {
+                    Some((min, "")) => (min.trim().parse().ok()?, usize::MAX),
+                    Some((min, max)) => (min.trim().parse().ok()?, max.trim().parse().ok()?),
+                    None => {
+                        let n = spec.trim().parse().ok()?;
+                        (n, n)
+                    }
+                };
+                res.push((atom, min, max));
+                continue;
+            }
+            _ => (1, 1),
+        };
+        if max != 1 || min != 1 {
+            chars.next();
+        }
+        res.push((atom, min, max))
+    }
+    Some(res)
+}
+
+/// Matches the whole text simulating all positions in the atoms at once,
+/// so the time is linear in the text length whatever the pattern is
+fn match_atoms(atoms: &[(Atom, usize, usize)], text: &str) -> bool {
+    // repetitions of an unbounded atom are counted up to its minimum only, more don't matter
+    let cap = |(_, min, max): &(Atom, usize, usize)| if *max == usize::MAX { *min } else { *max };
+    let mut offsets = Vec::with_capacity(atoms.len() + 1);
+    let mut size = 0;
+    for atom in atoms {
+        offsets.push(size);
+        size += cap(atom) + 1
+    }
+    // the accepting state after the last atom
+    offsets.push(size);
+    let mut seen = vec![false; size + 1];
+    let mut states = Vec::new();
+    add_state(atoms, &offsets, &mut seen, &mut states, 0, 0);
+    for c in text.chars() {
+        seen.fill(false);
+        let mut next = Vec::with_capacity(states.len());
+        for (i, count) in states {
+            if let Some(atom @ (class, _, max)) = atoms.get(i)
+                && count < *max
+                && class.matches(c)
+            {
+                add_state(
+                    atoms,
+                    &offsets,
+                    &mut seen,
+                    &mut next,
+                    i,
+                    (count + 1).min(cap(atom)),
+                )
+            }
+        }
+        if next.is_empty() {
+            return false;
+        }
+        states = next
+    }
+    states.iter().any(|&(i, _)| i == atoms.len())
+}
+
+/// Adds a state of an atom with a number of its repetitions,
+/// and following atoms when the minimum is reached
+fn add_state(
+    atoms: &[(Atom, usize, usize)],
+    offsets: &[usize],
+    seen: &mut [bool],
+    states: &mut Vec<(usize, usize)>,
+    mut i: usize,
+    mut count: usize,
+) {
+    loop {
+        let id = offsets[i] + count;
+        if seen[id] {
+            return;
+        }
+        seen[id] = true;
+        states.push((i, count));
+        match atoms.get(i) {
+            Some((_, min, _)) if count >= *min => {
+                i += 1;
+                count = 0
+            }
+            _ => return,
+        }
+    }
+}
+
+/// Matches a whole value against a simplified regular expression
+///
+/// Supported: literals, `.`, classes as `[a-z0-9_]` and `[^...]`, escapes `\d \w \s \D \W \S`,
+/// and quantifiers `* + ? {n} {n,} {n,m}`. Groups and alternations aren't supported.
+/// An invalid pattern never matches. The time is linear in the value length, so a pattern
+/// can't be made to hang on a crafted value.
+///
+/// # Examples
+/// ```
+/// assert!(pattern_match(r"\d{3}-\d{4}", "555-1234"));
+/// assert!(!pattern_match("[a-z]+", "Abc"));
+/// ```
+pub fn pattern_match(pat: &str, value: &str) -> bool {
+    match compile_pattern(pat) {
+        Some(atoms) => match_atoms(&atoms, value),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(pattern_match(r"\d{3}-\d{4}", "555-1234"));
+        assert!(!pattern_match(r"\d{3}-\d{4}", "555-12345"));
+        assert!(pattern_match("[a-z]+", "abc"));
+        assert!(!pattern_match("[a-z]+", "Abc"));
+        assert!(pattern_match("a?b*c{2,3}", "cc"));
+        assert!(pattern_match("a?b*c{2,3}", "abbbccc"));
+        assert!(!pattern_match("a?b*c{2,3}", "abcccc"));
+        assert!(pattern_match("x{0}y", "y"));
+        assert!(pattern_match("", ""));
+        assert!(!pattern_match("", "a"));
+        assert!(pattern_match("[^-]-[a-]", "x--"));
+    }
+
+    #[test]
+    fn no_exponential_backtracking() {
+        let value = "a".repeat(10_000);
+        assert!(!pattern_match("a*a*a*a*a*a*b", &value));
+        assert!(pattern_match("a*a*a*a*a*a*", &value));
+        assert!(!pattern_match("(a+)+b", &value));
+    }
+}